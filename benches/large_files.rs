@@ -0,0 +1,58 @@
+//! Measures the bundling of large generated files, such as lookup tables.
+//!
+//! Run with `cargo bench --bench large_files`.
+
+use cargo_simple_bundler::{Bundler, MemorySources};
+use std::{
+    fmt::Write as _,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+const ITERATIONS: usize = 5;
+
+/// Returns a module with `lines` lines of a lookup table, each item of which has a doc comment and
+/// refers to the crate.
+fn table_module(lines: usize) -> String {
+    let mut s = String::new();
+    for i in 0..lines / 2 {
+        writeln!(s, "/// The entry {} of the table. 表の要素", i).unwrap();
+        writeln!(s, "pub const T{}: crate::Value = crate::Value({}); // {:x}", i, i * 7 % 1009, i)
+            .unwrap();
+    }
+    s
+}
+
+fn bundler(lines: usize) -> Bundler {
+    let mut sources = MemorySources::new();
+    sources
+        .insert("/bench/src/lib.rs", "pub mod table;\npub struct Value(pub u32);\n")
+        .insert("/bench/src/table.rs", table_module(lines));
+    let mut bundler = Bundler::new("bench", Path::new("/bench/src"));
+    bundler.sources(sources);
+    bundler
+}
+
+/// Returns the median of the durations of bundling.
+fn measure(bundler: &Bundler) -> Duration {
+    let mut durations = (0..ITERATIONS)
+        .map(|_| {
+            let start = Instant::now();
+            bundler.bundle(&mut vec![]).unwrap();
+            start.elapsed()
+        })
+        .collect::<Vec<_>>();
+    durations.sort();
+    durations[ITERATIONS / 2]
+}
+
+fn main() {
+    for &lines in &[5_000, 20_000, 80_000] {
+        let mut bundler = bundler(lines);
+        println!("{:>6} lines, no options:           {:?}", lines, measure(&bundler));
+        bundler.remove_doc_comments(true);
+        println!("{:>6} lines, --remove-doc-comments: {:?}", lines, measure(&bundler));
+        bundler.remove_doc_comments(false).remove_comments(true);
+        println!("{:>6} lines, --remove-comments:     {:?}", lines, measure(&bundler));
+    }
+}
@@ -0,0 +1,236 @@
+use crate::{
+    bundle, common::*, emit_graph, explain, test_bundle, Config, Diagnostic, DiagnosticKind,
+    DiskSources, GraphFormat, IndentStyle, LineEnding, Report, SourceProvider, TestReport, Watcher,
+};
+use std::{
+    io::Write,
+    path::{Path as FsPath, PathBuf as FsPathBuf},
+    sync::Arc,
+};
+
+/// Builds the configuration of the bundling. Options which are not set are the same as the
+/// defaults of the command line.
+#[derive(Clone, Debug)]
+pub struct Bundler {
+    config: Config,
+}
+
+impl Bundler {
+    /// Bundles the crate `crate_ident` whose `lib.rs` is in `crate_src_dir`.
+    pub fn new(crate_ident: impl Into<String>, crate_src_dir: impl Into<FsPathBuf>) -> Self {
+        Bundler {
+            config: Config {
+                crate_ident: crate_ident.into(),
+                crate_src_dir: crate_src_dir.into(),
+                entry_file_path: None,
+                entry_source: None,
+                sources: Arc::new(DiskSources),
+                remove_test_modules: false,
+                remove_doc_comments: false,
+                remove_comments: false,
+                indent: IndentStyle::default(),
+                tab_width: 4,
+                reindent: false,
+                source_map_path: None,
+                provenance_comments: false,
+                provenance_commit: None,
+                max_blank_lines: None,
+                trim_trailing_whitespace: false,
+                trim_blank_lines_around_braces: false,
+                line_ending: LineEnding::default(),
+                deny_warnings: false,
+                allowed_warnings: vec![],
+                edition: "2018".to_owned(),
+                check: false,
+                cache_dir: None,
+            },
+        }
+    }
+
+    /// Bundles the library target of the root package of the manifest, or of the manifest found
+    /// from the current directory like `cargo` if `manifest_path` is `None`.
+    pub fn from_manifest(manifest_path: Option<&FsPath>) -> Result<Self> {
+        let metadata = {
+            let mut cmd = cargo_metadata::MetadataCommand::new();
+            if let Some(path) = manifest_path {
+                cmd.manifest_path(path);
+            }
+            cmd.exec().map_err(|err| Error::Metadata(err.to_string()))?
+        };
+        let package = metadata
+            .root_package()
+            .ok_or_else(|| Error::Metadata("root package not found".to_owned()))?;
+        let target = package
+            .targets
+            .iter()
+            .filter(|target| {
+                target.name == package.name && target.kind.iter().any(|kind| kind.ends_with("lib"))
+            })
+            .exactly_one()
+            .map_err(|_| {
+                Error::Metadata("target not found or multiple targets found".to_owned())
+            })?;
+        let crate_src_dir = target.src_path.parent().unwrap_or(&target.src_path);
+        let mut bundler = Bundler::new(package.name.replace('-', "_"), crate_src_dir);
+        bundler.edition(&*package.edition);
+        bundler.cache_dir(Some(metadata.target_directory.join("cargo-simple-bundler").into()));
+        Ok(bundler)
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Enables the dependency analysis from the entry file.
+    pub fn entry_file(&mut self, path: impl Into<FsPathBuf>) -> &mut Self {
+        self.config.entry_file_path = Some(path.into());
+        self
+    }
+
+    /// Enables the dependency analysis from the content of the entry file, which is not read
+    /// from the disk. The path of [`entry_file`](Bundler::entry_file) is only used in messages.
+    pub fn entry_source(&mut self, content: impl Into<String>) -> &mut Self {
+        self.config.entry_source = Some(content.into());
+        self
+    }
+
+    /// Reads the entry file and the files of the modules from `sources` instead of the disk.
+    pub fn sources(&mut self, sources: impl SourceProvider + 'static) -> &mut Self {
+        self.config.sources = Arc::new(sources);
+        self
+    }
+
+    pub fn remove_test_modules(&mut self, yes: bool) -> &mut Self {
+        self.config.remove_test_modules = yes;
+        self
+    }
+
+    pub fn remove_doc_comments(&mut self, yes: bool) -> &mut Self {
+        self.config.remove_doc_comments = yes;
+        self
+    }
+
+    pub fn remove_comments(&mut self, yes: bool) -> &mut Self {
+        self.config.remove_comments = yes;
+        self
+    }
+
+    pub fn indent(&mut self, indent: IndentStyle) -> &mut Self {
+        self.config.indent = indent;
+        self
+    }
+
+    pub fn tab_width(&mut self, tab_width: usize) -> &mut Self {
+        self.config.tab_width = tab_width;
+        self
+    }
+
+    pub fn reindent(&mut self, yes: bool) -> &mut Self {
+        self.config.reindent = yes;
+        self
+    }
+
+    /// Writes the source map of the output to `path`.
+    pub fn source_map(&mut self, path: impl Into<FsPathBuf>) -> &mut Self {
+        self.config.source_map_path = Some(path.into());
+        self
+    }
+
+    pub fn provenance_comments(&mut self, yes: bool) -> &mut Self {
+        self.config.provenance_comments = yes;
+        self
+    }
+
+    /// Includes the commit in the provenance comments.
+    pub fn provenance_commit(&mut self, commit: impl Into<String>) -> &mut Self {
+        self.config.provenance_commit = Some(commit.into());
+        self
+    }
+
+    pub fn max_blank_lines(&mut self, max: usize) -> &mut Self {
+        self.config.max_blank_lines = Some(max);
+        self
+    }
+
+    pub fn trim_trailing_whitespace(&mut self, yes: bool) -> &mut Self {
+        self.config.trim_trailing_whitespace = yes;
+        self
+    }
+
+    pub fn trim_blank_lines_around_braces(&mut self, yes: bool) -> &mut Self {
+        self.config.trim_blank_lines_around_braces = yes;
+        self
+    }
+
+    pub fn line_ending(&mut self, line_ending: LineEnding) -> &mut Self {
+        self.config.line_ending = line_ending;
+        self
+    }
+
+    pub fn deny_warnings(&mut self, yes: bool) -> &mut Self {
+        self.config.deny_warnings = yes;
+        self
+    }
+
+    /// Neither reports nor denies the kind of warnings.
+    pub fn allow(&mut self, kind: DiagnosticKind) -> &mut Self {
+        self.config.allowed_warnings.push(kind);
+        self
+    }
+
+    /// Sets the edition of the crate like `2018`, with which [`check`](Bundler::check) and
+    /// [`test`](Bundler::test) compile the bundle. It is the edition of the package with
+    /// [`from_manifest`](Bundler::from_manifest), and `2018` with [`new`](Bundler::new).
+    pub fn edition(&mut self, edition: impl Into<String>) -> &mut Self {
+        self.config.edition = edition.into();
+        self
+    }
+
+    /// Checks that the entry file and the bundle compile with `rustc` before writing the bundle.
+    pub fn check(&mut self, yes: bool) -> &mut Self {
+        self.config.check = yes;
+        self
+    }
+
+    /// Caches the analyzed files of the library in `dir`, or disables the cache if `None`. The
+    /// cache is in the target directory of the package by default with
+    /// [`from_manifest`](Bundler::from_manifest), and disabled with [`new`](Bundler::new).
+    pub fn cache_dir(&mut self, dir: Option<FsPathBuf>) -> &mut Self {
+        self.config.cache_dir = dir;
+        self
+    }
+
+    /// See [`bundle`].
+    pub fn bundle<W>(&self, writer: &mut W) -> Result<Report>
+    where
+        W: ?Sized + Write,
+    {
+        bundle(self.config.clone(), writer)
+    }
+
+    /// See [`explain`].
+    pub fn explain<W>(&self, module: &str, writer: &mut W) -> Result<Vec<Diagnostic>>
+    where
+        W: ?Sized + Write,
+    {
+        explain(self.config.clone(), module, writer)
+    }
+
+    /// See [`emit_graph`].
+    pub fn emit_graph<W>(&self, format: GraphFormat, writer: &mut W) -> Result<Vec<Diagnostic>>
+    where
+        W: ?Sized + Write,
+    {
+        emit_graph(self.config.clone(), format, writer)
+    }
+
+    /// See [`test_bundle`].
+    pub fn test(&self) -> Result<TestReport> {
+        test_bundle(self.config.clone())
+    }
+
+    /// See [`Watcher`].
+    pub fn watcher(&self) -> Watcher {
+        Watcher::new(self.config.clone())
+    }
+}
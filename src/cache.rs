@@ -0,0 +1,132 @@
+use crate::{common::*, path::Path};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    fs,
+    hash::Hasher,
+    path::{Path as FsPath, PathBuf as FsPathBuf},
+    process,
+};
+
+/// Changed whenever the format of the cached data changes.
+const FORMAT_VERSION: u32 = 4;
+
+/// Identifies a cached analysis. Each module, file and set of the removal options has one slot, a
+/// file in the cache directory, so that the entry of an old content is overwritten by the new one
+/// instead of piling up.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Key {
+    slot: u64,
+    /// Changes whenever the file or the options affecting the analysis change.
+    hash: u64,
+}
+
+/// Returns the key of the analysis of the file of the module.
+pub fn key(path: &Path, fs_path: &FsPath, content: &str, cx: &Context) -> Key {
+    let config = &cx.config;
+    let mut hasher = Fnv1a::default();
+    let module = format!("{:?}", with_context(path, cx));
+    let fs_path = fs_path.to_string_lossy();
+    for s in &[&*module, &fs_path, &config.crate_ident] {
+        hasher.write(s.as_bytes());
+        hasher.write_u8(0xff);
+    }
+    for &option in &[config.remove_test_modules, config.remove_doc_comments, config.remove_comments]
+    {
+        hasher.write_u8(option as u8);
+    }
+    let slot = hasher.finish();
+
+    hasher.write_u32(FORMAT_VERSION);
+    let mut allowed_warnings =
+        config.allowed_warnings.iter().map(|kind| kind.as_str()).collect_vec();
+    allowed_warnings.sort_unstable();
+    let mut strings = vec![env!("CARGO_PKG_VERSION")];
+    strings.extend(allowed_warnings);
+    strings.push(content);
+    for s in strings {
+        hasher.write(s.as_bytes());
+        hasher.write_u8(0xff);
+    }
+    Key { slot, hash: hasher.finish() }
+}
+
+fn cache_file(key: Key, cx: &Context) -> Option<FsPathBuf> {
+    let dir = cx.config.cache_dir.as_ref()?;
+    Some(dir.join(format!("{:016x}.json", key.slot)))
+}
+
+/// Returns the cached value, or `None` if it is missing, stale or broken. The first line of the
+/// file is the hash of the key, and the rest is the value in JSON.
+pub fn load<T: DeserializeOwned>(key: Key, cx: &Context) -> Option<T> {
+    let file = cache_file(key, cx)?;
+    let content = fs::read_to_string(&file).ok()?;
+    let (hash, json) = content.split_once('\n')?;
+    if hash != format!("{:016x}", key.hash) {
+        return None;
+    }
+    match serde_json::from_str(json) {
+        Ok(value) => Some(value),
+        Err(err) => {
+            log::debug!("ignore the broken cache `{}`: {}", file.display(), err);
+            None
+        }
+    }
+}
+
+/// Stores the value in the cache, replacing the entry in the same slot. Failures are only logged
+/// since the cache is optional.
+pub fn store<T: Serialize>(key: Key, value: &T, cx: &Context) {
+    let file = match cache_file(key, cx) {
+        Some(file) => file,
+        None => return,
+    };
+    // write to a temporary file first so that other processes never read a partial file
+    let temp_file = file.with_extension(format!("{}.tmp", process::id()));
+    let result = serde_json::to_string(value).map_err(Into::into).and_then(|json| {
+        fs::create_dir_all(file.parent().unwrap_or(&file))?;
+        fs::write(&temp_file, format!("{:016x}\n{}", key.hash, json))?;
+        fs::rename(&temp_file, &file)
+    });
+    if let Err(err) = result {
+        log::warn!("failed to write the cache `{}`: {}", file.display(), err);
+        let _ = fs::remove_file(&temp_file);
+    }
+}
+
+/// The 64-bit FNV-1a hash, which is stable unlike the hasher of the standard library.
+struct Fnv1a(u64);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Fnv1a(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for Fnv1a {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fnv1a_test() {
+        let hash = |s: &str| {
+            let mut hasher = Fnv1a::default();
+            hasher.write(s.as_bytes());
+            hasher.finish()
+        };
+        assert_eq!(hash(""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(hash("a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(hash("foobar"), 0x8594_4171_f739_67e8);
+    }
+}
@@ -0,0 +1,155 @@
+use crate::{common::*, content::Content, SourceLocation, SourceMap};
+use serde::Deserialize;
+use std::{
+    env, fmt, fs,
+    path::{Path as FsPath, PathBuf as FsPathBuf},
+    process::{self, Command},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// An error of `rustc` on the entry file and the bundle.
+#[derive(Clone, Debug)]
+pub struct CompilerMessage {
+    pub message: String,
+    /// The message rendered by `rustc`, which points into the temporary file.
+    pub rendered: String,
+    /// The line of the primary span in the original sources.
+    pub origin: Option<MessageOrigin>,
+}
+
+#[derive(Clone, Debug)]
+pub enum MessageOrigin {
+    /// A 1-based line of the entry file.
+    Entry {
+        file: FsPathBuf,
+        line: usize,
+    },
+    Library(SourceLocation),
+}
+
+impl fmt::Display for CompilerMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.rendered.trim_end())?;
+        match &self.origin {
+            Some(MessageOrigin::Entry { file, line }) => {
+                write!(f, "\nnote: from the entry file {}:{}", file.display(), line)
+            }
+            Some(MessageOrigin::Library(SourceLocation { module, file, line })) => {
+                write!(f, "\nnote: from {}:{} ({})", file.display(), line, module)
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct RustcDiagnostic {
+    message: String,
+    code: Option<serde_json::Value>,
+    level: String,
+    spans: Vec<RustcSpan>,
+    rendered: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RustcSpan {
+    line_start: usize,
+    is_primary: bool,
+}
+
+/// Compiles the entry file followed by the bundle, or the bundle alone as a library if the entry
+/// file is not specified, with `rustc --emit=metadata` in a temporary directory.
+pub fn check(entry: Option<(&FsPath, &str)>, content: &Content, cx: &Context) -> Result<()> {
+    if !cx.config.check {
+        return Ok(());
+    }
+    let bundle = content.to_string();
+    let (source, crate_type) = match entry {
+        Some((_, entry_content)) => (format!("{}\n{}", entry_content, bundle), "bin"),
+        None => (bundle, "lib"),
+    };
+
+    let dir = scratch_dir("check")?;
+    let output = run_rustc(&dir, &source, &cx.config.edition, crate_type);
+    let _ = fs::remove_dir_all(&dir);
+    let output = output?;
+
+    let source_map = SourceMap::new(content, cx)?;
+    let mut messages = String::from_utf8_lossy(&output.stderr)
+        .lines()
+        .filter_map(|line| serde_json::from_str::<RustcDiagnostic>(line).ok())
+        // skip the summaries like "aborting due to 2 previous errors"
+        .filter(|diagnostic| {
+            diagnostic.level == "error"
+                && (diagnostic.code.is_some() || !diagnostic.spans.is_empty())
+        })
+        .map(|RustcDiagnostic { message, spans, rendered, .. }| {
+            let line = spans.iter().find(|span| span.is_primary).map(|span| span.line_start);
+            let origin = line.and_then(|line| match source_map.locate(&source, line) {
+                Some(location) => Some(MessageOrigin::Library(location.clone())),
+                None => match entry {
+                    Some((file, entry_content)) if line <= entry_content.lines().count() => {
+                        Some(MessageOrigin::Entry { file: file.to_owned(), line })
+                    }
+                    _ => None,
+                },
+            });
+            CompilerMessage {
+                rendered: rendered.unwrap_or_else(|| message.clone()),
+                message,
+                origin,
+            }
+        })
+        .collect::<Vec<_>>();
+    if !output.status.success() && messages.is_empty() {
+        // `rustc` fails without errors in JSON, e.g. for an invalid edition or an ICE
+        let stderr = String::from_utf8_lossy(&output.stderr).trim_end().to_owned();
+        let rendered = if stderr.is_empty() {
+            format!("`rustc` failed with {}", output.status)
+        } else {
+            stderr
+        };
+        messages.push(CompilerMessage { message: rendered.clone(), rendered, origin: None });
+    }
+    if output.status.success() && messages.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::CompileFailed(messages))
+    }
+}
+
+/// Creates a new temporary directory, which the caller removes.
+pub fn scratch_dir(purpose: &str) -> Result<FsPathBuf> {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    let dir = env::temp_dir().join(format!(
+        "cargo-simple-bundler-{}-{}-{}",
+        purpose,
+        process::id(),
+        COUNT.fetch_add(1, Ordering::Relaxed),
+    ));
+    fs::create_dir_all(&dir).map_err(Error::write(Some(dir.clone())))?;
+    Ok(dir)
+}
+
+fn run_rustc(
+    dir: &FsPath,
+    source: &str,
+    edition: &str,
+    crate_type: &str,
+) -> Result<process::Output> {
+    let file = dir.join("main.rs");
+    fs::write(&file, source).map_err(Error::write(Some(file.clone())))?;
+    Command::new(env::var_os("RUSTC").unwrap_or_else(|| "rustc".into()))
+        .arg("--edition")
+        .arg(edition)
+        .arg("--crate-type")
+        .arg(crate_type)
+        .arg("--emit=metadata")
+        .arg("--error-format=json")
+        .arg("--cap-lints=allow")
+        .arg("--out-dir")
+        .arg(dir)
+        .arg(&file)
+        .output()
+        .map_err(Error::Rustc)
+}
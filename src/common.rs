@@ -1,19 +1,27 @@
-use crate::{file::FileInner, path::Path, Config};
+use crate::{
+    diagnostic::{Diagnostic, DiagnosticKind, Location},
+    file::FileInner,
+    interner::Interner,
+    path::Path,
+    report::Report,
+    Config,
+};
 use std::{
     collections::{HashMap, HashSet},
     fmt,
-    rc::Rc,
+    sync::Arc,
 };
-use string_interner::StringInterner;
 
-pub use anyhow::Result;
+pub use crate::error::{Error, Result};
+pub use crate::interner::Symbol;
 pub use itertools::{Itertools as _, PeekingNext as _};
-pub use string_interner::DefaultSymbol as Symbol;
 
 impl fmt::Debug for WithContext<'_, '_, Symbol> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let ident = self.cx.interner.resolve(*self.inner).unwrap();
-        write!(f, "{}", ident)
+        match self.cx.interner.resolve(*self.inner) {
+            Some(ident) => write!(f, "{}", ident),
+            None => write!(f, "{{unknown}}"),
+        }
     }
 }
 
@@ -26,14 +34,62 @@ impl fmt::Debug for WithContext<'_, '_, HashSet<Symbol>> {
 }
 
 pub struct Context {
-    pub config: Config,
-    pub interner: StringInterner,
-    pub files: HashMap<Path, Rc<FileInner>>,
+    pub config: Arc<Config>,
+    pub interner: Arc<Interner>,
+    pub files: HashMap<Path, Arc<FileInner>>,
+    pub report: Report,
+    /// The location of the `use` declaration from which each dependency is collected first.
+    pub use_locations: HashMap<Path, Location>,
+    /// The files used in the current run of the bundling. `files` may also contain the files
+    /// loaded in parallel in advance and the files parsed in the previous runs.
+    pub loaded: HashSet<Path>,
 }
 
 impl From<Config> for Context {
     fn from(config: Config) -> Self {
-        Context { config, interner: StringInterner::new(), files: HashMap::new() }
+        Context {
+            config: Arc::new(config),
+            interner: Arc::new(Interner::default()),
+            files: HashMap::new(),
+            report: Report::default(),
+            use_locations: HashMap::new(),
+            loaded: HashSet::new(),
+        }
+    }
+}
+
+impl Context {
+    /// Clears the results of the previous run of the bundling, keeping the parsed files.
+    pub fn start_run(&mut self) {
+        self.report = Report::default();
+        self.use_locations.clear();
+        self.loaded.clear();
+    }
+
+    /// Returns a context sharing the configuration and the interner, with which another thread
+    /// loads files.
+    pub fn fork(&self) -> Context {
+        Context {
+            config: Arc::clone(&self.config),
+            interner: Arc::clone(&self.interner),
+            files: HashMap::new(),
+            report: Report::default(),
+            use_locations: HashMap::new(),
+            loaded: HashSet::new(),
+        }
+    }
+
+    pub fn resolve_symbol(&self, symbol: Symbol) -> Result<Arc<str>> {
+        self.interner
+            .resolve(symbol)
+            .ok_or_else(|| Error::Resolution(format!("unknown symbol `{:?}`", symbol)))
+    }
+
+    pub fn warn(&mut self, kind: DiagnosticKind, message: String, location: Option<Location>) {
+        log::debug!("{}", message);
+        if !self.config.allowed_warnings.contains(&kind) {
+            self.report.warnings.push(Diagnostic { kind, message, location });
+        }
     }
 }
 
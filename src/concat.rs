@@ -1,4 +1,14 @@
-use crate::{common::*, content::Content, deps::Deps, file::file, path::Path, span::take_spans};
+use crate::{
+    common::*,
+    content::{Content, IndentStyle},
+    deps::Deps,
+    diagnostic::SourceFile,
+    file::{file, fs_path, relative_fs_path},
+    path::Path,
+    report::ModuleReport,
+    source_map::Origin,
+    span::{take_spans, Span},
+};
 use std::collections::{HashMap, HashSet};
 
 pub fn concat_contents(deps: &Deps, cx: &mut Context) -> Result<Content> {
@@ -17,13 +27,30 @@ pub fn concat_contents(deps: &Deps, cx: &mut Context) -> Result<Content> {
         }
     }
     let mut acc = Content::from(format!("mod {} ", cx.config.crate_ident));
-    inside_block(&mut acc, cx.config.indent_spaces, |acc| {
+    let comment = provenance_comment(&Path::default(), cx)?;
+    inside_block(&mut acc, cx.config.indent, comment, |acc| {
         do_concat_contents(&Path::default(), &inners, acc, cx)
     })?;
     acc.push_line("");
+    normalize(&mut acc, cx);
     Ok(acc)
 }
 
+fn normalize(acc: &mut Content, cx: &Context) {
+    if cx.config.reindent {
+        acc.reindent(cx.config.indent, cx.config.tab_width);
+    }
+    if cx.config.trim_trailing_whitespace {
+        acc.trim_trailing_whitespace();
+    }
+    if cx.config.trim_blank_lines_around_braces {
+        acc.trim_blank_lines_around_braces();
+    }
+    if let Some(max) = cx.config.max_blank_lines {
+        acc.collapse_blank_lines(max);
+    }
+}
+
 fn do_concat_contents(
     path: &Path,
     inners: &HashMap<Path, HashSet<Symbol>>,
@@ -34,16 +61,28 @@ fn do_concat_contents(
     let mut target_spans = file.target_spans().clone();
     let mut replace_with = vec![];
 
+    // reserve the place of the module so that the modules are reported in the order of the output
+    let report_index = cx.report.included_modules.len();
+    cx.report.included_modules.push(ModuleReport {
+        path: format!("{:?}", with_context(path, cx)),
+        file: relative_fs_path(path, cx)?,
+        bytes: 0,
+    });
+    cx.report.bytes_saved += file.removed_bytes();
+
     for child_module in file.child_modules() {
-        if inners.get(path).map_or(true, |x| x.contains(&child_module.symbol())) {
+        if !matches!(inners.get(path), Some(x) if !x.contains(&child_module.symbol())) {
             let path = child_module.path();
             let mut acc = Content::default();
             acc.push(" ");
-            inside_block(&mut acc, cx.config.indent_spaces, |acc| {
+            let comment = provenance_comment(&path, cx)?;
+            inside_block(&mut acc, cx.config.indent, comment, |acc| {
                 do_concat_contents(&path, inners, acc, cx)
             })?;
             replace_with.push((child_module.item_mod_semi_span(), acc));
         } else {
+            let path = format!("{:?}", with_context(&child_module.path(), cx));
+            cx.report.removed_modules.push(path);
             target_spans.remove(child_module.item_mod_span());
             if let Some(span) = child_module.item_use_span() {
                 target_spans.remove(span);
@@ -58,43 +97,95 @@ fn do_concat_contents(
     replace_with.sort_unstable_by_key(|&(span, _)| span);
     let mut replace_with = replace_with.into_iter().peekable();
 
-    for chunk in take_spans(file.content(), &target_spans) {
+    let overlapping_spans = |span: Span, cx: &Context| -> Result<Error> {
+        let fs_path = fs_path(path, cx)?;
+        let location = SourceFile { file: &fs_path, content: file.content() }.locate(span.start);
+        let module = format!("{:?}", with_context(path, cx));
+        Ok(Error::OverlappingSpans { module, location })
+    };
+
+    let line_index = file.line_index();
+    let mut bytes = 0;
+    for chunk in take_spans(file.content(), line_index, &target_spans) {
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(span) => return Err(overlapping_spans(span, cx)?),
+        };
+        bytes += chunk.content.len();
         for _ in 0..chunk.line_offset {
             acc.push_line("");
         }
+        acc.set_origin(Origin { path: path.clone(), line: chunk.span.start.line });
         for _ in 0..chunk.column_offset {
             acc.push(" ");
         }
 
-        replace_with
-            .peeking_take_while(|&(span, _)| span.start < chunk.span.start)
-            .inspect(|(span, _)| assert!(span.end <= chunk.span.start))
-            .for_each(drop);
+        // replacements in the removed code are dropped along with it
+        for (span, _) in replace_with.peeking_take_while(|&(span, _)| span.start < chunk.span.start)
+        {
+            if span.end > chunk.span.start {
+                return Err(overlapping_spans(span, cx)?);
+            }
+        }
 
         let mut rest = chunk.content;
-        let mut offset = chunk.span.start.column;
+        let mut offset = chunk.offset;
 
         for (span, s) in replace_with.peeking_take_while(|&(span, _)| span.start < chunk.span.end) {
-            assert!(span.end <= chunk.span.end);
-            acc.push(&rest[..span.start.column - offset]);
+            let split = line_index.offset(span.start).zip(line_index.offset(span.end)).and_then(
+                |(start, end)| {
+                    let before = rest.get(..start.checked_sub(offset)?)?;
+                    let after = rest.get(end.checked_sub(offset)?..)?;
+                    Some((before, after, end))
+                },
+            );
+            let (before, after, end) = match split {
+                Some(split) if span.end <= chunk.span.end => split,
+                _ => return Err(overlapping_spans(span, cx)?),
+            };
+            acc.push(before);
             acc.append(s);
-            rest = &rest[span.end.column - offset..];
-            offset = span.end.column;
+            rest = after;
+            offset = end;
         }
         acc.push(rest);
     }
+    cx.report.included_modules[report_index].bytes = bytes;
     Ok(())
 }
 
-fn inside_block<F>(acc: &mut Content, indent_spaces: usize, f: F) -> Result<()>
+/// Returns the comment naming the file of the module, which is put on the line of the opening
+/// brace so that the lines of the module stay where they are.
+fn provenance_comment(path: &Path, cx: &Context) -> Result<Option<String>> {
+    if !cx.config.provenance_comments {
+        return Ok(None);
+    }
+    let file = relative_fs_path(path, cx)?;
+    Ok(Some(match &cx.config.provenance_commit {
+        Some(commit) => format!("// {} @ {}", file, commit),
+        None => format!("// {}", file),
+    }))
+}
+
+fn inside_block<F>(
+    acc: &mut Content,
+    indent: IndentStyle,
+    comment: Option<String>,
+    f: F,
+) -> Result<()>
 where
     F: FnOnce(&mut Content) -> Result<()>,
 {
     let mut s = Content::default();
     f(&mut s)?;
-    s.indent(indent_spaces);
+    s.indent(indent);
 
-    acc.push_line("{");
+    acc.push("{");
+    if let Some(comment) = comment {
+        acc.push(" ");
+        acc.push(&comment);
+    }
+    acc.push_line("");
     acc.append(s);
     acc.push_line("");
     acc.push("}");
@@ -1,23 +1,78 @@
+use crate::source_map::Origin;
 use std::fmt;
 
+/// How the contents of blocks are indented.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum IndentStyle {
+    /// Indents with the specified number of spaces. `Spaces(0)` disables indentation.
+    Spaces(usize),
+    /// Indents with a tab.
+    Tabs,
+}
+
+impl Default for IndentStyle {
+    fn default() -> Self {
+        IndentStyle::Spaces(0)
+    }
+}
+
+impl IndentStyle {
+    fn unit(self) -> String {
+        match self {
+            IndentStyle::Spaces(n) => " ".repeat(n),
+            IndentStyle::Tabs => "\t".to_owned(),
+        }
+    }
+
+    /// Returns whitespace of the style which is `columns` wide.
+    fn whitespace(self, columns: usize, tab_width: usize) -> String {
+        match self {
+            IndentStyle::Spaces(_) => " ".repeat(columns),
+            IndentStyle::Tabs if tab_width == 0 => " ".repeat(columns),
+            IndentStyle::Tabs => {
+                "\t".repeat(columns / tab_width) + &" ".repeat(columns % tab_width)
+            }
+        }
+    }
+}
+
+/// The line ending of the output.
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    Crlf,
+}
+
 #[derive(Debug)]
 struct Line {
-    spaces: usize,
+    indent: String,
     s: String,
+    origin: Option<Origin>,
 }
 
 impl From<String> for Line {
     fn from(s: String) -> Self {
-        Line { spaces: 0, s }
+        Line { indent: String::new(), s, origin: None }
+    }
+}
+
+impl Line {
+    fn is_blank(&self) -> bool {
+        self.s.trim().is_empty()
+    }
+
+    fn opens_block(&self) -> bool {
+        // a trailing comment is allowed, such as the provenance comment
+        let s = self.s.trim_end();
+        s.ends_with('{')
+            || matches!(s.rsplit_once(" // "), Some((s, _)) if s.trim_end().ends_with('{'))
     }
 }
 
 impl fmt::Display for Line {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        for _ in 0..self.spaces {
-            write!(f, " ")?;
-        }
-        write!(f, "{}", self.s)
+        write!(f, "{}{}", self.indent, self.s)
     }
 }
 
@@ -25,17 +80,18 @@ impl fmt::Display for Line {
 pub struct Content {
     lines: Vec<Line>,
     new_line: bool,
+    pending_origin: Option<Origin>,
 }
 
 impl Default for Content {
     fn default() -> Self {
-        Content { lines: vec![], new_line: true }
+        Content { lines: vec![], new_line: true, pending_origin: None }
     }
 }
 
 impl From<String> for Content {
     fn from(s: String) -> Self {
-        Content { lines: vec![Line::from(s)], new_line: false }
+        Content { lines: vec![Line::from(s)], new_line: false, pending_origin: None }
     }
 }
 
@@ -59,7 +115,8 @@ impl fmt::Display for Content {
 impl Content {
     fn push_inner(&mut self, s: &str) {
         if self.new_line {
-            self.lines.push(Line::from(s.to_owned()));
+            let origin = self.pending_origin.take();
+            self.lines.push(Line { origin, ..Line::from(s.to_owned()) });
         } else {
             self.lines.last_mut().unwrap().s.push_str(s);
         }
@@ -81,7 +138,7 @@ impl Content {
             if self.new_line {
                 self.lines.push(first);
             } else {
-                assert_eq!(first.spaces, 0);
+                self.push(&first.indent);
                 self.push_line(&first.s);
             }
             for line in lines {
@@ -91,10 +148,98 @@ impl Content {
         self.new_line = other.new_line;
     }
 
-    pub fn indent(&mut self, spaces: usize) {
+    pub fn indent(&mut self, style: IndentStyle) {
+        let unit = style.unit();
         for line in &mut self.lines {
-            line.spaces += spaces;
+            line.indent.push_str(&unit);
+        }
+    }
+
+    /// Rewrites the leading whitespace of the original lines in `style`, regarding a tab as
+    /// `tab_width` columns wide.
+    pub fn reindent(&mut self, style: IndentStyle, tab_width: usize) {
+        for line in &mut self.lines {
+            let len = line.s.len() - line.s.trim_start_matches(&[' ', '\t'][..]).len();
+            let columns = line.s[..len].chars().fold(0, |columns, c| match c {
+                '\t' if tab_width > 0 => (columns / tab_width + 1) * tab_width,
+                _ => columns + 1,
+            });
+            line.s.replace_range(..len, &style.whitespace(columns, tab_width));
+        }
+    }
+
+    /// Marks the current line, or the next one if a new line is expected, as originating from
+    /// `origin`. A line that already has an origin keeps it.
+    pub fn set_origin(&mut self, origin: Origin) {
+        match self.lines.last_mut() {
+            Some(line) if !self.new_line => {
+                line.origin.get_or_insert(origin);
+            }
+            _ => self.pending_origin = Some(origin),
+        }
+    }
+
+    /// Removes trailing whitespace from every line. Indentation of empty lines is removed as well.
+    pub fn trim_trailing_whitespace(&mut self) {
+        for line in &mut self.lines {
+            line.s.truncate(line.s.trim_end().len());
+            if line.s.is_empty() {
+                line.indent.clear();
+            }
+        }
+    }
+
+    /// Collapses each run of blank lines into at most `max` lines.
+    pub fn collapse_blank_lines(&mut self, max: usize) {
+        let mut run = 0;
+        self.lines.retain(|line| {
+            if line.is_blank() {
+                run += 1;
+                run <= max
+            } else {
+                run = 0;
+                true
+            }
+        });
+    }
+
+    /// Removes blank lines directly after a line ending with `{` and directly before a line
+    /// starting with `}`.
+    pub fn trim_blank_lines_around_braces(&mut self) {
+        let mut retain = vec![true; self.lines.len()];
+        let mut after_open = false;
+        for (line, retain) in self.lines.iter().zip(&mut retain) {
+            if line.is_blank() {
+                *retain = !after_open;
+            } else {
+                after_open = line.opens_block();
+            }
+        }
+        let mut before_close = false;
+        for (line, retain) in self.lines.iter().zip(&mut retain).rev() {
+            if line.is_blank() {
+                *retain &= !before_close;
+            } else {
+                before_close = line.s.trim_start().starts_with('}');
+            }
         }
+        let mut retain = retain.into_iter();
+        self.lines.retain(|_| retain.next().unwrap());
+    }
+
+    /// Returns the origin of each line of the output, in order.
+    pub fn line_origins(&self) -> Vec<Option<Origin>> {
+        let mut origins = vec![];
+        for line in &self.lines {
+            for (i, _) in line.s.split('\n').enumerate() {
+                let origin = line
+                    .origin
+                    .as_ref()
+                    .map(|origin| Origin { path: origin.path.clone(), line: origin.line + i });
+                origins.push(origin);
+            }
+        }
+        origins
     }
 }
 
@@ -172,10 +317,83 @@ mod tests {
         s.push_line("a");
         let mut t = Content::default();
         t.push_line("b");
-        t.indent(2);
+        t.indent(IndentStyle::Spaces(2));
         s.append(t);
         s.push_line("c");
 
         assert_eq!(s.to_string(), "a\n  b\nc\n");
     }
+
+    fn lines(lines: &[&str]) -> Content {
+        let mut s = Content::default();
+        for line in lines {
+            s.push_line(line);
+        }
+        s
+    }
+
+    #[test]
+    fn trim_trailing_whitespace() {
+        let mut s = lines(&["a  ", "", "b"]);
+        s.indent(IndentStyle::Spaces(2));
+        s.trim_trailing_whitespace();
+
+        assert_eq!(s.to_string(), "  a\n\n  b\n");
+    }
+
+    #[test]
+    fn collapse_blank_lines() {
+        let mut s = lines(&["a", "", "", "", "b", "", "c", " ", "", "d"]);
+        s.collapse_blank_lines(1);
+
+        assert_eq!(s.to_string(), "a\n\nb\n\nc\n \nd\n");
+    }
+
+    #[test]
+    fn trim_blank_lines_around_braces() {
+        let mut s = lines(&["a {", "", "  b", "", "  c", " ", "}", "", "d"]);
+        s.trim_blank_lines_around_braces();
+
+        assert_eq!(s.to_string(), "a {\n  b\n\n  c\n}\n\nd\n");
+    }
+
+    #[test]
+    fn indent_tabs() {
+        let mut s = lines(&["a", "b"]);
+        s.indent(IndentStyle::Tabs);
+        s.indent(IndentStyle::Tabs);
+
+        assert_eq!(s.to_string(), "\t\ta\n\t\tb\n");
+    }
+
+    #[test]
+    fn reindent_tabs_to_spaces() {
+        let mut s = lines(&["\ta", "\t  b", "  \tc", "d\te"]);
+        s.reindent(IndentStyle::Spaces(4), 4);
+
+        assert_eq!(s.to_string(), "    a\n      b\n    c\nd\te\n");
+    }
+
+    #[test]
+    fn reindent_spaces_to_tabs() {
+        let mut s = lines(&["    a", "      b", "\t    c"]);
+        s.reindent(IndentStyle::Tabs, 4);
+
+        assert_eq!(s.to_string(), "\ta\n\t  b\n\t\tc\n");
+    }
+
+    #[test]
+    fn line_origins() {
+        let origin = |line| Origin { path: Default::default(), line };
+        let mut s = Content::default();
+        s.push_line("{");
+        s.set_origin(origin(3));
+        s.push("a\nb");
+        s.set_origin(origin(7));
+        s.push_line("c");
+        s.push_line("}");
+
+        let lines = s.line_origins().into_iter().map(|x| x.map(|x| x.line)).collect::<Vec<_>>();
+        assert_eq!(lines, [None, Some(3), Some(4), None]);
+    }
 }
@@ -1,12 +1,16 @@
-use crate::{common::*, path::Path};
-use std::{
-    collections::{hash_set, HashSet},
-    fmt, iter,
+use crate::{
+    common::*,
+    diagnostic::{DiagnosticKind, SourceFile},
+    path::Path,
 };
-use syn::visit::Visit;
+use indexmap::{set, IndexMap, IndexSet};
+use std::{fmt, iter};
+use syn::{spanned::Spanned as _, visit::Visit};
 
+/// The dependencies in the order in which they are found, so that the bundling does not depend on
+/// the hashes of the paths.
 #[derive(Default)]
-pub struct Deps(HashSet<Path>);
+pub struct Deps(IndexSet<Path>);
 
 impl fmt::Debug for WithContext<'_, '_, Deps> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -31,8 +35,8 @@ impl Deps {
         self.0.iter()
     }
 
-    pub fn drain(&mut self) -> hash_set::Drain<Path> {
-        self.0.drain()
+    pub fn drain(&mut self) -> set::Drain<'_, Path> {
+        self.0.drain(..)
     }
 
     pub fn insert(&mut self, path: Path) -> bool {
@@ -42,7 +46,7 @@ impl Deps {
 
 impl IntoIterator for Deps {
     type Item = Path;
-    type IntoIter = hash_set::IntoIter<Path>;
+    type IntoIter = set::IntoIter<Path>;
 
     fn into_iter(self) -> Self::IntoIter {
         self.0.into_iter()
@@ -55,28 +59,80 @@ impl Extend<Path> for Deps {
     }
 }
 
-pub fn entry_deps(entry_syn_file: &syn::File, cx: &mut Context) -> Deps {
+/// The line of the `use` declaration from which each dependency is collected first.
+pub type UseLines = IndexMap<Path, usize>;
+
+fn collect_from_item_use<F>(
+    item_use: &syn::ItemUse,
+    deps: &mut Deps,
+    use_lines: &mut UseLines,
+    src: SourceFile<'_>,
+    cx: &mut Context,
+    f: F,
+) where
+    F: FnOnce(&mut Deps, &mut Context),
+{
+    let mut item_use_deps = Deps::default();
+    f(&mut item_use_deps, cx);
+    let line = item_use.span().start().line;
+    for path in item_use_deps {
+        use_lines.entry(path.clone()).or_insert(line);
+        cx.use_locations.entry(path.clone()).or_insert_with(|| src.locate_span(item_use.span()));
+        deps.insert(path);
+    }
+}
+
+pub fn entry_deps(
+    entry_syn_file: &syn::File,
+    src: SourceFile<'_>,
+    cx: &mut Context,
+) -> (Deps, UseLines) {
     struct Visitor<'a> {
         deps: &'a mut Deps,
+        use_lines: &'a mut UseLines,
+        src: SourceFile<'a>,
         cx: &'a mut Context,
     }
 
     impl<'a> Visit<'_> for Visitor<'a> {
         fn visit_item_use(&mut self, item_use: &syn::ItemUse) {
-            collect_entry_deps_from_use_tree(&item_use.tree, self.deps, self.cx);
+            let src = self.src;
+            collect_from_item_use(item_use, self.deps, self.use_lines, src, self.cx, |deps, cx| {
+                collect_entry_deps_from_use_tree(&item_use.tree, deps, src, cx)
+            });
             syn::visit::visit_item_use(self, item_use);
         }
     }
 
     let mut deps = Deps::default();
-    Visitor { deps: &mut deps, cx }.visit_file(entry_syn_file);
-    deps
+    let mut use_lines = UseLines::new();
+    Visitor { deps: &mut deps, use_lines: &mut use_lines, src, cx }.visit_file(entry_syn_file);
+    (deps, use_lines)
 }
 
-fn collect_entry_deps_from_use_tree(tree: &syn::UseTree, deps: &mut Deps, cx: &mut Context) {
+/// Collects the dependencies of a `use` declaration of the module `path`.
+pub fn collect_deps_from_item_use(
+    path: &Path,
+    item_use: &syn::ItemUse,
+    deps: &mut Deps,
+    use_lines: &mut UseLines,
+    src: SourceFile<'_>,
+    cx: &mut Context,
+) {
+    collect_from_item_use(item_use, deps, use_lines, src, cx, |deps, cx| {
+        collect_deps_from_use_tree(path, &item_use.tree, deps, src, cx)
+    });
+}
+
+fn collect_entry_deps_from_use_tree(
+    tree: &syn::UseTree,
+    deps: &mut Deps,
+    src: SourceFile<'_>,
+    cx: &mut Context,
+) {
     match tree {
         syn::UseTree::Path(syn::UsePath { ident, tree, .. }) if ident == &cx.config.crate_ident => {
-            collect_deps_from_use_subtree(&Path::default(), tree, deps, cx);
+            collect_deps_from_use_subtree(&Path::default(), tree, deps, src, cx);
         }
         syn::UseTree::Name(syn::UseName { ident, .. })
         | syn::UseTree::Rename(syn::UseRename { ident, .. })
@@ -86,34 +142,37 @@ fn collect_entry_deps_from_use_tree(tree: &syn::UseTree, deps: &mut Deps, cx: &m
         }
         syn::UseTree::Group(syn::UseGroup { items, .. }) => {
             for tree in items {
-                collect_entry_deps_from_use_tree(tree, deps, cx);
+                collect_entry_deps_from_use_tree(tree, deps, src, cx);
             }
         }
         _ => {}
     }
 }
 
-pub fn collect_deps_from_use_tree(
+fn collect_deps_from_use_tree(
     path: &Path,
     tree: &syn::UseTree,
     deps: &mut Deps,
+    src: SourceFile<'_>,
     cx: &mut Context,
 ) {
     match &tree {
         syn::UseTree::Path(syn::UsePath { ident, tree: subtree, .. }) => {
             if ident == "crate" {
-                collect_deps_from_use_subtree(&Path::default(), subtree, deps, cx);
+                collect_deps_from_use_subtree(&Path::default(), subtree, deps, src, cx);
             } else if ident == "self" {
-                collect_deps_from_use_subtree(path, subtree, deps, cx);
+                collect_deps_from_use_subtree(path, subtree, deps, src, cx);
             } else if ident == "super" {
-                collect_deps_from_use_subtree(path, tree, deps, cx);
+                collect_deps_from_use_subtree(path, tree, deps, src, cx);
             } else if ident != "std" {
-                log::warn!("skip the use declaration started with `{}`", ident);
+                let message = format!("skip the use declaration started with `{}`", ident);
+                let location = src.locate_span(ident.span());
+                cx.warn(DiagnosticKind::UnsupportedUse, message, Some(location));
             }
         }
         syn::UseTree::Group(syn::UseGroup { items, .. }) => {
             for tree in items {
-                collect_deps_from_use_tree(path, tree, deps, cx);
+                collect_deps_from_use_tree(path, tree, deps, src, cx);
             }
         }
         _ => {}
@@ -124,19 +183,22 @@ fn collect_deps_from_use_subtree(
     path: &Path,
     tree: &syn::UseTree,
     deps: &mut Deps,
+    src: SourceFile<'_>,
     cx: &mut Context,
 ) {
     match tree {
         syn::UseTree::Path(syn::UsePath { ident, tree, .. }) => {
             if ident == "super" {
                 if let Some(parent) = path.parent() {
-                    collect_deps_from_use_subtree(&parent, tree, deps, cx);
+                    collect_deps_from_use_subtree(&parent, tree, deps, src, cx);
                 } else {
-                    log::error!("too many `super` keywords. skip");
+                    let message = "too many `super` keywords. skip".to_owned();
+                    let location = src.locate_span(ident.span());
+                    cx.warn(DiagnosticKind::TooManySuper, message, Some(location));
                 }
             } else {
                 let symbol = cx.interner.get_or_intern(ident.to_string());
-                collect_deps_from_use_subtree(&path.child(symbol), tree, deps, cx);
+                collect_deps_from_use_subtree(&path.child(symbol), tree, deps, src, cx);
             }
         }
         syn::UseTree::Name(syn::UseName { ident, .. })
@@ -153,7 +215,7 @@ fn collect_deps_from_use_subtree(
         }
         syn::UseTree::Group(syn::UseGroup { items, .. }) => {
             for tree in items {
-                collect_deps_from_use_subtree(path, tree, deps, cx);
+                collect_deps_from_use_subtree(path, tree, deps, src, cx);
             }
         }
     }
@@ -0,0 +1,183 @@
+use crate::{
+    common::*,
+    span::{byte_column, char_column, LineColumn},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    path::{Path as FsPath, PathBuf as FsPathBuf},
+    str::FromStr,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DiagnosticKind {
+    /// The definition of a path is not found, so the path is treated as its parent.
+    DefinitionNotFound,
+    /// An inline module is kept as it is, without analyzing its dependencies.
+    InlineModule,
+    /// A `use` declaration started with an unsupported keyword or crate is skipped.
+    UnsupportedUse,
+    /// A `use` declaration with `pub(restricted)` is skipped.
+    RestrictedUse,
+    /// A `use` declaration goes above the root with `super`.
+    TooManySuper,
+}
+
+impl DiagnosticKind {
+    pub const ALL: [DiagnosticKind; 5] = [
+        DiagnosticKind::DefinitionNotFound,
+        DiagnosticKind::InlineModule,
+        DiagnosticKind::UnsupportedUse,
+        DiagnosticKind::RestrictedUse,
+        DiagnosticKind::TooManySuper,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DiagnosticKind::DefinitionNotFound => "definition-not-found",
+            DiagnosticKind::InlineModule => "inline-module",
+            DiagnosticKind::UnsupportedUse => "unsupported-use",
+            DiagnosticKind::RestrictedUse => "restricted-use",
+            DiagnosticKind::TooManySuper => "too-many-super",
+        }
+    }
+}
+
+impl FromStr for DiagnosticKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        DiagnosticKind::ALL.iter().copied().find(|kind| kind.as_str() == s).ok_or_else(|| {
+            let kinds = DiagnosticKind::ALL.iter().map(|kind| kind.as_str()).join("`, `");
+            Error::InvalidConfig(format!(
+                "unknown kind of warnings `{}`. expected one of `{}`",
+                s, kinds,
+            ))
+        })
+    }
+}
+
+/// A problem found while analyzing the crate, which does not stop the bundling.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Diagnostic {
+    pub kind: DiagnosticKind,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub location: Option<Location>,
+}
+
+/// A location in a source file, with the line at the location.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Location {
+    pub file: FsPathBuf,
+    /// 1-based line number.
+    pub line: usize,
+    /// 0-based column number, counted in characters.
+    pub column: usize,
+    pub snippet: String,
+}
+
+/// A source file being analyzed, in which diagnostics are located.
+#[derive(Clone, Copy)]
+pub struct SourceFile<'a> {
+    pub file: &'a FsPath,
+    pub content: &'a str,
+}
+
+impl<'a> SourceFile<'a> {
+    pub fn locate(self, LineColumn { line, column }: LineColumn) -> Location {
+        let snippet = self.line(line);
+        let column = char_column(snippet, column);
+        Location { file: self.file.to_owned(), line, column, snippet: snippet.to_owned() }
+    }
+
+    /// Locates the start of the span of `proc_macro2`.
+    pub fn locate_span(self, span: proc_macro2::Span) -> Location {
+        let proc_macro2::LineColumn { line, column } = span.start();
+        self.locate(LineColumn { line, column: byte_column(self.line(line), column) })
+    }
+
+    fn line(self, line: usize) -> &'a str {
+        self.content.lines().nth(line.saturating_sub(1)).unwrap_or_default()
+    }
+}
+
+impl Location {
+    /// Returns `file:line:column`, where the column is 1-based.
+    pub fn short(&self) -> String {
+        format!("{}:{}:{}", self.file.display(), self.line, self.column + 1)
+    }
+}
+
+/// Renders the location with the snippet and a caret under the column.
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Location { line, column, snippet, .. } = self;
+        let gutter = " ".repeat(line.to_string().len());
+        // keep tabs so that the caret is under the column
+        let pad = snippet
+            .chars()
+            .take(*column)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect::<String>();
+        writeln!(f, "{}--> {}", gutter, self.short())?;
+        writeln!(f, "{} |", gutter)?;
+        writeln!(f, "{} | {}", line, snippet)?;
+        write!(f, "{} | {}^", gutter, pad)
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "warning[{}]: {}", self.kind.as_str(), self.message)?;
+        if let Some(location) = &self.location {
+            write!(f, "\n{}", location)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kind_from_str_test() {
+        for &kind in &DiagnosticKind::ALL {
+            assert_eq!(kind.as_str().parse::<DiagnosticKind>().unwrap(), kind);
+        }
+        assert!("unknown".parse::<DiagnosticKind>().is_err());
+    }
+
+    #[test]
+    fn display_test() {
+        let src =
+            SourceFile { file: FsPath::new("src/a.rs"), content: "mod b;\n\tuse foo::Bar;\n" };
+        let diagnostic = Diagnostic {
+            kind: DiagnosticKind::UnsupportedUse,
+            message: "skip the use declaration started with `foo`".to_owned(),
+            location: Some(src.locate(LineColumn { line: 2, column: 5 })),
+        };
+        let expected = "\
+warning[unsupported-use]: skip the use declaration started with `foo`
+ --> src/a.rs:2:6
+  |
+2 | \tuse foo::Bar;
+  | \t    ^";
+        assert_eq!(diagnostic.to_string(), expected);
+
+        // the column of the location counts characters
+        let src = SourceFile { file: FsPath::new("src/a.rs"), content: "/* α */ use foo::Bar;\n" };
+        assert_eq!(src.locate(LineColumn { line: 1, column: 9 }).short(), "src/a.rs:1:9");
+        let tokens = src.content.parse::<proc_macro2::TokenStream>().unwrap();
+        let span = tokens.into_iter().next().unwrap().span();
+        assert_eq!(src.locate_span(span).short(), "src/a.rs:1:9");
+
+        let diagnostic = Diagnostic { location: None, ..diagnostic };
+        assert_eq!(
+            diagnostic.to_string(),
+            "warning[unsupported-use]: skip the use declaration started with `foo`",
+        );
+    }
+}
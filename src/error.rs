@@ -0,0 +1,157 @@
+use crate::{
+    check::CompilerMessage,
+    diagnostic::{Diagnostic, Location},
+};
+use std::{fmt, io, path::PathBuf as FsPathBuf};
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// The error of the library. Locations are 1-based lines and 0-based columns counted in
+/// characters, as in [`Location`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// Failed to read the entry file.
+    EntryRead { file: FsPathBuf, source: io::Error },
+    /// The entry file is not valid Rust.
+    EntryParse { location: Location, source: syn::Error },
+    /// The file of a module of the library is missing or cannot be read.
+    ModuleMissing {
+        /// The path of the module like `crate::a::b`.
+        module: String,
+        file: FsPathBuf,
+        /// The location of the `mod` item declaring the module.
+        declared_at: Option<Box<Location>>,
+        source: io::Error,
+    },
+    /// The file of a module of the library is not valid Rust.
+    ModuleParse {
+        /// The path of the module like `crate::a::b`.
+        module: String,
+        location: Location,
+        /// The location of the `mod` item declaring the module.
+        declared_at: Option<Box<Location>>,
+        source: syn::Error,
+    },
+    /// Failed to read a file other than the sources, such as a source map.
+    Read { file: FsPathBuf, source: io::Error },
+    /// A source map is not valid.
+    SourceMapParse { file: FsPathBuf, source: serde_json::Error },
+    /// Failed to get the metadata of the package with `cargo metadata`.
+    Metadata(String),
+    /// Failed to write the output or a file.
+    Write { file: Option<FsPathBuf>, source: io::Error },
+    /// A module or a path cannot be resolved.
+    Resolution(String),
+    /// A replacement in a module overlaps with another replacement or the code around it.
+    OverlappingSpans { module: String, location: Location },
+    /// The configuration is invalid.
+    InvalidConfig(String),
+    /// Warnings are emitted while they are denied.
+    DeniedWarnings(Vec<Diagnostic>),
+    /// Failed to run `rustc` to check the bundle.
+    Rustc(io::Error),
+    /// The entry file and the bundle do not compile.
+    CompileFailed(Vec<CompilerMessage>),
+    /// Failed to run `cargo` to test the bundle.
+    Cargo(io::Error),
+    /// The tests of the original crate or the bundle cannot be built.
+    TestBuild { dir: FsPathBuf, message: String },
+}
+
+impl Error {
+    pub fn write(file: Option<FsPathBuf>) -> impl FnOnce(io::Error) -> Error {
+        |source| Error::Write { file, source }
+    }
+}
+
+/// Errors of the writers of the output.
+impl From<io::Error> for Error {
+    fn from(source: io::Error) -> Self {
+        Error::Write { file: None, source }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::EntryRead { file, source } => {
+                write!(f, "failed to read the entry file `{}`: {}", file.display(), source)
+            }
+            Error::EntryParse { location, source } => {
+                write!(f, "failed to parse the entry file: {}\n{}", source, location)
+            }
+            Error::ModuleMissing { module, file, declared_at, source } => {
+                let file = file.display();
+                write!(f, "failed to read `{}` for the module `{}`: {}", file, module, source)?;
+                write_declared_at(f, declared_at)
+            }
+            Error::ModuleParse { module, location, declared_at, source } => {
+                write!(f, "failed to parse the module `{}`: {}\n{}", module, source, location)?;
+                write_declared_at(f, declared_at)
+            }
+            Error::Read { file, source } => {
+                write!(f, "failed to read `{}`: {}", file.display(), source)
+            }
+            Error::SourceMapParse { file, source } => {
+                write!(f, "failed to parse the source map `{}`: {}", file.display(), source)
+            }
+            Error::Write { file: Some(file), source } => {
+                write!(f, "failed to write `{}`: {}", file.display(), source)
+            }
+            Error::Write { file: None, source } => {
+                write!(f, "failed to write the output: {}", source)
+            }
+            Error::Metadata(message) => write!(f, "failed to get the metadata: {}", message),
+            Error::Resolution(message) | Error::InvalidConfig(message) => write!(f, "{}", message),
+            Error::OverlappingSpans { module, location } => {
+                write!(f, "overlapping replacements in the module `{}`\n{}", module, location)
+            }
+            Error::DeniedWarnings(warnings) => {
+                write!(f, "aborting due to {} denied warning(s)", warnings.len())?;
+                for warning in warnings {
+                    write!(f, "\n\n{}", warning)?;
+                }
+                Ok(())
+            }
+            Error::Rustc(source) => write!(f, "failed to run `rustc`: {}", source),
+            Error::CompileFailed(messages) => {
+                write!(f, "the bundle does not compile")?;
+                for message in messages {
+                    write!(f, "\n\n{}", message)?;
+                }
+                Ok(())
+            }
+            Error::Cargo(source) => write!(f, "failed to run `cargo`: {}", source),
+            Error::TestBuild { dir, message } => {
+                write!(f, "failed to build the tests in `{}`:\n{}", dir.display(), message)
+            }
+        }
+    }
+}
+
+fn write_declared_at(
+    f: &mut fmt::Formatter<'_>,
+    declared_at: &Option<Box<Location>>,
+) -> fmt::Result {
+    match declared_at {
+        Some(location) => write!(f, "\nnote: the module is declared at {}", location.short()),
+        None => Ok(()),
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::EntryRead { source, .. }
+            | Error::ModuleMissing { source, .. }
+            | Error::Read { source, .. }
+            | Error::Write { source, .. }
+            | Error::Rustc(source)
+            | Error::Cargo(source) => Some(source),
+            Error::EntryParse { source, .. } | Error::ModuleParse { source, .. } => Some(source),
+            Error::SourceMapParse { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use crate::{common::*, deps::UseLines, file::relative_fs_path, path::Path};
+use indexmap::IndexMap;
+use std::{
+    collections::{HashMap, VecDeque},
+    io::Write,
+};
+
+/// The location of a `use` declaration.
+#[derive(Clone, PartialEq, Eq)]
+pub struct UseSite {
+    /// The module containing the declaration, or `None` for the entry file.
+    pub module: Option<Path>,
+    pub line: usize,
+}
+
+/// Why a module is included in the bundle, as a step from another module.
+#[derive(Clone)]
+enum Step {
+    /// A `use` declaration at `line` names `use_path`, which is resolved as the module.
+    Use { use_path: Path, line: usize },
+    /// The module is an ancestor of the module the step comes from.
+    Ancestor,
+    /// The module is a descendant of the module the step comes from.
+    Descendant,
+}
+
+/// Records every edge found while resolving the dependencies, from which the chain of the
+/// dependencies leading to a module is reconstructed.
+#[derive(Default)]
+pub struct Causes {
+    // ordered so that the chosen chain does not depend on the hashes of the paths
+    use_sites: IndexMap<Path, Vec<UseSite>>,
+    resolutions: IndexMap<Path, Vec<Path>>,
+    inclusions: Vec<(Path, Path, Step)>,
+}
+
+impl Causes {
+    pub fn from_entry(use_lines: &UseLines) -> Self {
+        let mut causes = Causes::default();
+        for (path, &line) in use_lines {
+            causes.add_use_site(path, UseSite { module: None, line });
+        }
+        causes
+    }
+
+    pub fn add_use_site(&mut self, use_path: &Path, site: UseSite) {
+        let sites = self.use_sites.entry(use_path.clone()).or_default();
+        if !sites.contains(&site) {
+            sites.push(site);
+        }
+    }
+
+    pub fn add_resolution(&mut self, use_path: &Path, path: &Path) {
+        self.resolutions.entry(use_path.clone()).or_default().push(path.clone());
+    }
+
+    pub fn add_ancestor(&mut self, of: &Path, ancestor: &Path) {
+        self.inclusions.push((of.clone(), ancestor.clone(), Step::Ancestor));
+    }
+
+    pub fn add_descendant(&mut self, of: &Path, descendant: &Path) {
+        self.inclusions.push((of.clone(), descendant.clone(), Step::Descendant));
+    }
+
+    fn edges(&self) -> HashMap<Option<Path>, Vec<(Path, Step)>> {
+        let mut edges = HashMap::<_, Vec<_>>::new();
+        for (use_path, sites) in &self.use_sites {
+            for path in self.resolutions.get(use_path).into_iter().flatten() {
+                for UseSite { module, line } in sites {
+                    let step = Step::Use { use_path: use_path.clone(), line: *line };
+                    edges.entry(module.clone()).or_default().push((path.clone(), step));
+                }
+            }
+        }
+        for (from, to, step) in &self.inclusions {
+            edges.entry(Some(from.clone())).or_default().push((to.clone(), step.clone()));
+        }
+        edges
+    }
+
+    /// Returns a shortest chain of steps from the entry file to `target`.
+    fn chain(&self, target: &Path) -> Option<Vec<(Option<Path>, Path, Step)>> {
+        let edges = self.edges();
+        let mut prev = HashMap::<Path, (Option<Path>, Step)>::new();
+        let mut queue = VecDeque::from(vec![None]);
+        while let Some(from) = queue.pop_front() {
+            if from.as_ref() == Some(target) {
+                break;
+            }
+            for (to, step) in edges.get(&from).into_iter().flatten() {
+                if !prev.contains_key(to) {
+                    prev.insert(to.clone(), (from.clone(), step.clone()));
+                    queue.push_back(Some(to.clone()));
+                }
+            }
+        }
+
+        let mut chain = vec![];
+        let mut to = target.clone();
+        while let Some((from, step)) = prev.get(&to) {
+            chain.push((from.clone(), to.clone(), step.clone()));
+            match from {
+                None => {
+                    chain.reverse();
+                    return Some(chain);
+                }
+                Some(from) => to = from.clone(),
+            }
+        }
+        None
+    }
+}
+
+/// Writes the chain of dependencies from the entry file to the module `target`.
+pub fn write_explanation<W>(
+    target: &Path,
+    causes: &Causes,
+    writer: &mut W,
+    cx: &Context,
+) -> Result<()>
+where
+    W: ?Sized + Write,
+{
+    let chain = causes.chain(target).ok_or_else(|| {
+        Error::Resolution(format!("`{:?}` is not included in the bundle", with_context(target, cx)))
+    })?;
+    for (from, to, step) in chain {
+        match (from, step) {
+            (from, Step::Use { use_path, line }) => {
+                let file = match (&from, &cx.config.entry_file_path) {
+                    (None, Some(entry_file_path)) => entry_file_path.display().to_string(),
+                    (None, None) => "the entry file".to_owned(),
+                    (Some(from), _) => relative_fs_path(from, cx)?,
+                };
+                writeln!(
+                    writer,
+                    "{}:{}: `use` of `{:?}` requires `{:?}`",
+                    file,
+                    line,
+                    with_context(&use_path, cx),
+                    with_context(&to, cx),
+                )?;
+            }
+            (Some(from), Step::Ancestor) => writeln!(
+                writer,
+                "`{:?}` is kept as an ancestor of `{:?}`",
+                with_context(&to, cx),
+                with_context(&from, cx),
+            )?,
+            (Some(from), Step::Descendant) => writeln!(
+                writer,
+                "`{:?}` is included as a part of `{:?}`",
+                with_context(&to, cx),
+                with_context(&from, cx),
+            )?,
+            (None, _) => unreachable!(),
+        }
+    }
+    Ok(())
+}
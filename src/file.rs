@@ -1,15 +1,21 @@
 use crate::{
+    cache,
     common::*,
-    deps::{collect_deps_from_use_tree, Deps},
+    deps::{collect_deps_from_item_use, Deps, UseLines},
+    diagnostic::{Diagnostic, DiagnosticKind, Location, SourceFile},
     path::Path,
-    span::{LineColumn, Span, SpanUnion},
+    report::RemovedBytes,
+    span::{count_code_bytes, span_str, LineColumn, LineIndex, Span, SpanUnion},
 };
+use indexmap::IndexMap;
 use quote::ToTokens as _;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
 use std::{
-    collections::{HashMap, HashSet},
-    fmt, fs,
-    path::PathBuf as FsPathBuf,
-    rc::Rc,
+    collections::HashSet,
+    fmt, mem,
+    path::{Path as FsPath, PathBuf as FsPathBuf},
+    sync::Arc,
 };
 use syn::{
     spanned::Spanned as _,
@@ -18,7 +24,7 @@ use syn::{
 
 pub struct File<'a> {
     path: &'a Path,
-    inner: Rc<FileInner>,
+    inner: Arc<FileInner>,
 }
 
 impl fmt::Debug for WithContext<'_, '_, File<'_>> {
@@ -44,10 +50,18 @@ impl File<'_> {
         &self.inner.content
     }
 
+    pub fn line_index(&self) -> &LineIndex {
+        &self.inner.line_index
+    }
+
     pub fn deps(&self) -> &Deps {
         &self.inner.deps
     }
 
+    pub fn use_line(&self, path: &Path) -> Option<usize> {
+        self.inner.use_lines.get(path).copied()
+    }
+
     pub fn contains_public_symbol(&self, symbol: Symbol) -> bool {
         self.inner.public_symbols.contains(&symbol)
     }
@@ -63,16 +77,27 @@ impl File<'_> {
     pub fn target_spans(&self) -> &SpanUnion {
         &self.inner.target_spans
     }
+
+    pub fn removed_bytes(&self) -> RemovedBytes {
+        self.inner.removed_bytes
+    }
 }
 
 #[derive(Default)]
 pub struct FileInner {
     content: String,
+    line_index: LineIndex,
     deps: Deps,
+    use_lines: UseLines,
     public_symbols: HashSet<Symbol>,
-    child_modules: HashMap<Symbol, ChildModuleInner>,
+    child_modules: IndexMap<Symbol, ChildModuleInner>,
     crate_keyword_spans: Vec<Span>,
     target_spans: SpanUnion,
+    removed_bytes: RemovedBytes,
+    /// The warnings and the locations of the `use` declarations recorded while parsing, which are
+    /// recorded again when the file is reused in another run of the bundling.
+    warnings: Vec<Diagnostic>,
+    use_locations: Vec<(Path, Location)>,
 }
 
 impl fmt::Debug for WithContext<'_, '_, FileInner> {
@@ -86,29 +111,152 @@ impl fmt::Debug for WithContext<'_, '_, FileInner> {
 }
 
 impl FileInner {
-    fn parse(content: String, syn_file: &syn::File, path: &Path, cx: &mut Context) -> FileInner {
+    fn parse(
+        content: String,
+        syn_file: &syn::File,
+        path: &Path,
+        fs_path: &FsPath,
+        cx: &mut Context,
+    ) -> FileInner {
         log::debug!("analyzing the file: {:?}", with_context(path, cx));
 
+        let line_index = LineIndex::new(&content);
         let public_symbols = public_symbols(syn_file, cx);
-        let mut child_modules = child_modules(syn_file, cx);
-        collect_reexports(syn_file, &mut child_modules, cx);
+        let mut child_modules = child_modules(syn_file, &line_index, cx);
+        collect_reexports(syn_file, &mut child_modules, &line_index, cx);
 
-        let target_spans = target_spans(&content, syn_file, cx);
+        let mut removed_bytes = RemovedBytes::default();
+        let target_spans = target_spans(&content, &line_index, syn_file, &mut removed_bytes, cx);
 
         let mut file = FileInner {
             content,
+            line_index,
             public_symbols,
             child_modules,
             target_spans,
+            removed_bytes,
             ..FileInner::default()
         };
-        Visitor1 { file: &mut file, path, cx }.visit_file(syn_file);
+        Visitor1 { file: &mut file, path, fs_path, cx }.visit_file(syn_file);
         Visitor2 { file: &mut file }.visit_file(syn_file);
 
         log::debug!("the file analyzed: {:?}", with_context(&file, cx));
 
         file
     }
+
+    fn to_cached(&self, cx: &Context) -> Result<CachedFile> {
+        let symbols = |set: &HashSet<Symbol>| -> Result<Vec<String>> {
+            set.iter().map(|&symbol| Ok(cx.resolve_symbol(symbol)?.to_string())).collect()
+        };
+        let child_modules = self.child_modules.iter().map(|(&symbol, child_module)| {
+            Ok(CachedChildModule {
+                symbol: cx.resolve_symbol(symbol)?.to_string(),
+                reexports: match &child_module.reexports {
+                    Reexports::Glob => None,
+                    Reexports::Group(set) => Some(symbols(set)?),
+                },
+                item_mod_span: child_module.item_mod_span,
+                item_mod_semi_span: child_module.item_mod_semi_span,
+                item_use_span: child_module.item_use_span,
+            })
+        });
+        Ok(CachedFile {
+            deps: self.deps.iter().map(|path| path.segments(cx)).collect::<Result<_>>()?,
+            use_lines: self
+                .use_lines
+                .iter()
+                .map(|(path, &line)| Ok((path.segments(cx)?, line)))
+                .collect::<Result<_>>()?,
+            public_symbols: symbols(&self.public_symbols)?,
+            child_modules: child_modules.collect::<Result<_>>()?,
+            crate_keyword_spans: self.crate_keyword_spans.clone(),
+            target_spans: self.target_spans.iter().collect(),
+            removed_bytes: self.removed_bytes,
+            warnings: self.warnings.clone(),
+            use_locations: self
+                .use_locations
+                .iter()
+                .map(|(path, location)| Ok((path.segments(cx)?, location.clone())))
+                .collect::<Result<_>>()?,
+        })
+    }
+
+    fn from_cached(content: String, cached: CachedFile, cx: &mut Context) -> FileInner {
+        let symbols = |names: Vec<String>, cx: &mut Context| {
+            names.into_iter().map(|name| cx.interner.get_or_intern(name)).collect::<HashSet<_>>()
+        };
+        let mut deps = Deps::default();
+        deps.extend(cached.deps.iter().map(|segments| Path::from_segments(segments, cx)));
+        FileInner {
+            line_index: LineIndex::new(&content),
+            content,
+            deps,
+            use_lines: cached
+                .use_lines
+                .iter()
+                .map(|(segments, line)| (Path::from_segments(segments, cx), *line))
+                .collect(),
+            public_symbols: symbols(cached.public_symbols, cx),
+            child_modules: cached
+                .child_modules
+                .into_iter()
+                .map(|child_module| {
+                    let reexports = match child_module.reexports {
+                        None => Reexports::Glob,
+                        Some(names) => Reexports::Group(symbols(names, cx)),
+                    };
+                    let inner = ChildModuleInner {
+                        reexports,
+                        item_mod_span: child_module.item_mod_span,
+                        item_mod_semi_span: child_module.item_mod_semi_span,
+                        item_use_span: child_module.item_use_span,
+                    };
+                    (cx.interner.get_or_intern(child_module.symbol), inner)
+                })
+                .collect(),
+            crate_keyword_spans: cached.crate_keyword_spans,
+            target_spans: cached.target_spans.into_iter().collect(),
+            removed_bytes: cached.removed_bytes,
+            warnings: cached.warnings,
+            use_locations: cached
+                .use_locations
+                .into_iter()
+                .map(|(segments, location)| (Path::from_segments(&segments, cx), location))
+                .collect(),
+        }
+    }
+
+    fn replay(&self, cx: &mut Context) {
+        cx.report.warnings.extend(self.warnings.iter().cloned());
+        for (path, location) in &self.use_locations {
+            cx.use_locations.entry(path.clone()).or_insert_with(|| location.clone());
+        }
+    }
+}
+
+/// [`FileInner`] without the content, in which the symbols are stored as strings.
+#[derive(Serialize, Deserialize)]
+struct CachedFile {
+    deps: Vec<Vec<String>>,
+    use_lines: Vec<(Vec<String>, usize)>,
+    public_symbols: Vec<String>,
+    child_modules: Vec<CachedChildModule>,
+    crate_keyword_spans: Vec<Span>,
+    target_spans: Vec<Span>,
+    removed_bytes: RemovedBytes,
+    warnings: Vec<Diagnostic>,
+    use_locations: Vec<(Vec<String>, Location)>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CachedChildModule {
+    symbol: String,
+    /// `None` for the glob.
+    reexports: Option<Vec<String>>,
+    item_mod_span: Span,
+    item_mod_semi_span: Span,
+    item_use_span: Option<Span>,
 }
 
 fn public_symbols(syn_file: &syn::File, cx: &mut Context) -> HashSet<Symbol> {
@@ -119,7 +267,6 @@ fn public_symbols(syn_file: &syn::File, cx: &mut Context) -> HashSet<Symbol> {
             syn::Item::Const(syn::ItemConst { vis, ident, .. })
             | syn::Item::Enum(syn::ItemEnum { vis, ident, .. })
             | syn::Item::Fn(syn::ItemFn { vis, sig: syn::Signature { ident, .. }, .. })
-            | syn::Item::Macro2(syn::ItemMacro2 { vis, ident, .. })
             | syn::Item::Static(syn::ItemStatic { vis, ident, .. })
             | syn::Item::Struct(syn::ItemStruct { vis, ident, .. })
             | syn::Item::Trait(syn::ItemTrait { vis, ident, .. })
@@ -131,21 +278,45 @@ fn public_symbols(syn_file: &syn::File, cx: &mut Context) -> HashSet<Symbol> {
                 let symbol = cx.interner.get_or_intern(ident.to_string());
                 Some(symbol)
             }
+            syn::Item::Verbatim(tokens) => {
+                let ident = public_macro_ident(tokens)?;
+                Some(cx.interner.get_or_intern(ident.to_string()))
+            }
             _ => None,
         })
         .collect()
 }
 
-fn child_modules(syn_file: &syn::File, cx: &mut Context) -> HashMap<Symbol, ChildModuleInner> {
+/// Returns the name of a `pub macro` item, which the parser leaves as verbatim tokens.
+fn public_macro_ident(tokens: &proc_macro2::TokenStream) -> Option<syn::Ident> {
+    let parser = |input: syn::parse::ParseStream<'_>| {
+        input.call(syn::Attribute::parse_outer)?;
+        let vis = input.parse::<syn::Visibility>()?;
+        input.parse::<syn::Token![macro]>()?;
+        let ident = input.parse::<syn::Ident>()?;
+        input.parse::<proc_macro2::TokenStream>()?;
+        Ok((vis, ident))
+    };
+    match syn::parse::Parser::parse2(parser, tokens.clone()) {
+        Ok((syn::Visibility::Public(_), ident)) => Some(ident),
+        _ => None,
+    }
+}
+
+fn child_modules(
+    syn_file: &syn::File,
+    line_index: &LineIndex,
+    cx: &mut Context,
+) -> IndexMap<Symbol, ChildModuleInner> {
     syn_file
         .items
         .iter()
         .filter_map(|item| match item {
-            syn::Item::Mod(item_mod) if item_mod.content.is_none() => {
+            syn::Item::Mod(item_mod @ syn::ItemMod { content: None, semi: Some(semi), .. }) => {
                 let symbol = cx.interner.get_or_intern(item_mod.ident.to_string());
                 let child_module = ChildModuleInner {
-                    item_mod_span: item_mod.span().into(),
-                    item_mod_semi_span: item_mod.semi.unwrap().span().into(),
+                    item_mod_span: line_index.span(item_mod.span()),
+                    item_mod_semi_span: line_index.span(semi.span()),
                     ..ChildModuleInner::default()
                 };
                 Some((symbol, child_module))
@@ -157,20 +328,22 @@ fn child_modules(syn_file: &syn::File, cx: &mut Context) -> HashMap<Symbol, Chil
 
 fn collect_reexports(
     syn_file: &syn::File,
-    child_modules: &mut HashMap<Symbol, ChildModuleInner>,
+    child_modules: &mut IndexMap<Symbol, ChildModuleInner>,
+    line_index: &LineIndex,
     cx: &mut Context,
 ) {
     for item_use in syn_file.items.iter().filter_map(|item| match item {
         syn::Item::Use(item_use) => Some(item_use),
         _ => None,
     }) {
-        do_collect_reexports(item_use, child_modules, cx);
+        do_collect_reexports(item_use, child_modules, line_index, cx);
     }
 }
 
 fn do_collect_reexports(
     item_use: &syn::ItemUse,
-    child_modules: &mut HashMap<Symbol, ChildModuleInner>,
+    child_modules: &mut IndexMap<Symbol, ChildModuleInner>,
+    line_index: &LineIndex,
     cx: &mut Context,
 ) {
     let syn::ItemUse { vis, tree, .. } = item_use;
@@ -221,14 +394,31 @@ fn do_collect_reexports(
         }
         _ => return,
     };
-    child_module.item_use_span = Some(item_use.span().into());
+    child_module.item_use_span = Some(line_index.span(item_use.span()));
 }
 
-fn target_spans(content: &str, syn_file: &syn::File, cx: &mut Context) -> SpanUnion {
-    let mut token_spans = SpanUnion::default();
-    collect_token_spans(syn_file.to_token_stream(), &mut token_spans);
+fn target_spans(
+    content: &str,
+    line_index: &LineIndex,
+    syn_file: &syn::File,
+    removed_bytes: &mut RemovedBytes,
+    cx: &mut Context,
+) -> SpanUnion {
+    let mut token_spans = vec![];
+    collect_token_spans(syn_file.to_token_stream(), line_index, &mut token_spans);
+    let token_spans = token_spans.into_iter().collect::<SpanUnion>();
 
     if cx.config.remove_comments {
+        // everything between the tokens is whitespace or comments
+        let mut prev_end = Some(0);
+        for span in token_spans.iter() {
+            let gap = prev_end.zip(line_index.offset(span.start));
+            let gap = gap.and_then(|(start, end)| content.get(start..end)).unwrap_or_default();
+            removed_bytes.comments += count_code_bytes(gap);
+            prev_end = line_index.offset(span.end);
+        }
+        let gap = prev_end.and_then(|start| content.get(start..)).unwrap_or_default();
+        removed_bytes.comments += count_code_bytes(gap);
         return token_spans;
     }
 
@@ -246,33 +436,37 @@ fn target_spans(content: &str, syn_file: &syn::File, cx: &mut Context) -> SpanUn
             }
         })
         .peekable();
-    let mut content_lines = (1..).zip(content.lines().map(str::len));
-    let mut target_spans = SpanUnion::default();
+    let mut content_lines = (1..=line_index.line_count())
+        .map(|line| (line, line_index.line_len(line).unwrap_or_default()));
+    let mut target_spans = vec![];
 
     while let Some((line, column)) = content_lines.next() {
         let end = match joint_lines.peeking_next(|&(x, _)| x == line) {
             None => LineColumn { line, column },
-            Some((_, end_line)) => {
-                let (_, end_column) = content_lines.nth(end_line - line - 1).unwrap();
-                LineColumn { line: end_line, column: end_column }
-            }
+            Some((_, end_line)) => match content_lines.nth(end_line - line - 1) {
+                Some((_, end_column)) => LineColumn { line: end_line, column: end_column },
+                None => LineColumn { line, column },
+            },
         };
-        let span = Span { start: LineColumn { line, column: 0 }, end };
-        target_spans.insert(span);
+        target_spans.push(Span { start: LineColumn { line, column: 0 }, end });
     }
 
-    target_spans
+    target_spans.into_iter().collect()
 }
 
-fn collect_token_spans(tokens: proc_macro2::TokenStream, acc: &mut SpanUnion) {
+fn collect_token_spans(
+    tokens: proc_macro2::TokenStream,
+    line_index: &LineIndex,
+    acc: &mut Vec<Span>,
+) {
     for token in tokens {
         match token {
             proc_macro2::TokenTree::Group(group) => {
-                acc.insert(group.span_open().into());
-                collect_token_spans(group.stream(), acc);
-                acc.insert(group.span_close().into());
+                acc.push(line_index.span(group.span_open()));
+                collect_token_spans(group.stream(), line_index, acc);
+                acc.push(line_index.span(group.span_close()));
             }
-            _ => acc.insert(token.span().into()),
+            _ => acc.push(line_index.span(token.span())),
         }
     }
 }
@@ -280,24 +474,48 @@ fn collect_token_spans(tokens: proc_macro2::TokenStream, acc: &mut SpanUnion) {
 struct Visitor1<'a> {
     file: &'a mut FileInner,
     path: &'a Path,
+    fs_path: &'a FsPath,
     cx: &'a mut Context,
 }
 
+impl Visitor1<'_> {
+    fn remove(&mut self, span: Span) -> usize {
+        self.file.target_spans.remove(span);
+        count_code_bytes(span_str(&self.file.content, &self.file.line_index, span))
+    }
+
+    fn locate(&self, span: proc_macro2::Span) -> Location {
+        SourceFile { file: self.fs_path, content: &self.file.content }.locate_span(span)
+    }
+}
+
 impl<'a> Visit<'_> for Visitor1<'a> {
     fn visit_attribute(&mut self, attr: &syn::Attribute) {
-        if self.cx.config.remove_doc_comments && attr.path.is_ident("doc") {
-            self.file.target_spans.remove(attr.span().into());
+        if self.cx.config.remove_doc_comments && attr.path().is_ident("doc") {
+            self.file.removed_bytes.doc_comments +=
+                self.remove(self.file.line_index.span(attr.span()));
         }
         visit::visit_attribute(self, attr);
     }
 
     fn visit_item_use(&mut self, item_use: &syn::ItemUse) {
         match item_use.vis {
-            syn::Visibility::Crate(_) | syn::Visibility::Restricted(_) => {
-                log::warn!("skip a use declaration with `pub(restricted)`");
+            syn::Visibility::Restricted(_) => {
+                let message = "skip a use declaration with `pub(restricted)`".to_owned();
+                let location = self.locate(item_use.span());
+                self.cx.warn(DiagnosticKind::RestrictedUse, message, Some(location));
             }
             syn::Visibility::Inherited => {
-                collect_deps_from_use_tree(self.path, &item_use.tree, &mut self.file.deps, self.cx);
+                let file = &mut *self.file;
+                let src = SourceFile { file: self.fs_path, content: &file.content };
+                collect_deps_from_item_use(
+                    self.path,
+                    item_use,
+                    &mut file.deps,
+                    &mut file.use_lines,
+                    src,
+                    self.cx,
+                );
             }
             _ => {}
         }
@@ -309,9 +527,12 @@ impl<'a> Visit<'_> for Visitor1<'a> {
             if self.cx.config.remove_test_modules
                 && item_mod.attrs.iter().any(is_cfg_test_attribute)
             {
-                self.file.target_spans.remove(item_mod.span().into());
+                self.file.removed_bytes.test_modules +=
+                    self.remove(self.file.line_index.span(item_mod.span()));
             } else {
-                log::warn!("skip the inline module `{}`", item_mod.ident);
+                let message = format!("skip the inline module `{}`", item_mod.ident);
+                let location = self.locate(item_mod.ident.span());
+                self.cx.warn(DiagnosticKind::InlineModule, message, Some(location));
             }
             return;
         }
@@ -336,41 +557,52 @@ struct Visitor2<'a> {
 
 impl Visit<'_> for Visitor2<'_> {
     fn visit_item_use(&mut self, item_use: &syn::ItemUse) {
-        collect_crate_keyword_spans(&item_use.tree, &mut self.file.crate_keyword_spans);
+        let file = &mut *self.file;
+        collect_crate_keyword_spans(
+            &item_use.tree,
+            &file.line_index,
+            &mut file.crate_keyword_spans,
+        );
         visit::visit_item_use(self, item_use);
     }
 
     fn visit_item_macro(&mut self, item_macro: &syn::ItemMacro) {
+        let file = &mut *self.file;
         collect_dollar_crate_keyword_spans(
             item_macro.mac.tokens.clone(),
-            &mut self.file.crate_keyword_spans,
+            &file.line_index,
+            &mut file.crate_keyword_spans,
         );
         visit::visit_item_macro(self, item_macro);
     }
 }
 
-fn collect_crate_keyword_spans(tree: &syn::UseTree, spans: &mut Vec<Span>) {
+fn collect_crate_keyword_spans(tree: &syn::UseTree, line_index: &LineIndex, spans: &mut Vec<Span>) {
     match tree {
         syn::UseTree::Path(syn::UsePath { ident, .. }) if ident == "crate" => {
-            spans.push(ident.span().into());
+            spans.push(line_index.span(ident.span()));
         }
         syn::UseTree::Group(syn::UseGroup { items, .. }) => {
             for tree in items {
-                collect_crate_keyword_spans(tree, spans);
+                collect_crate_keyword_spans(tree, line_index, spans);
             }
         }
         _ => {}
     }
 }
 
-fn collect_dollar_crate_keyword_spans(tokens: proc_macro2::TokenStream, spans: &mut Vec<Span>) {
+fn collect_dollar_crate_keyword_spans(
+    tokens: proc_macro2::TokenStream,
+    line_index: &LineIndex,
+    spans: &mut Vec<Span>,
+) {
     let mut iter = itertools::multipeek(tokens.clone().into_iter().tuple_windows());
     while let Some((token0, token1)) = iter.next() {
         let span = match (token0, token1) {
             (proc_macro2::TokenTree::Punct(punct), proc_macro2::TokenTree::Ident(ident))
                 if punct.as_char() == '$' && ident == "crate" =>
             {
-                ident.span().into()
+                line_index.span(ident.span())
             }
             _ => continue,
         };
@@ -393,30 +625,158 @@ fn collect_dollar_crate_keyword_spans(tokens: proc_macro2::TokenStream, spans: &
         proc_macro2::TokenTree::Group(group) => Some(group),
         _ => None,
     }) {
-        collect_dollar_crate_keyword_spans(group.stream(), spans);
+        collect_dollar_crate_keyword_spans(group.stream(), line_index, spans);
     }
 }
 
 pub fn file<'a>(path: &'a Path, cx: &mut Context) -> Result<File<'a>> {
     if let Some(file) = cx.files.get(path) {
-        return Ok(File { path, inner: Rc::clone(file) });
+        let file = Arc::clone(file);
+        if cx.loaded.insert(path.clone()) {
+            // loaded in parallel in advance, or in a previous run of the bundling
+            file.replay(cx);
+        }
+        return Ok(File { path, inner: file });
     }
-    let fs_path = fs_path(path, cx);
-    let content = fs::read_to_string(&fs_path)?;
-    let syn_file = syn::parse_file(&content)?;
-    let file = Rc::new(FileInner::parse(content, &syn_file, path, cx));
-    cx.files.insert(path.clone(), Rc::clone(&file));
+    let file = Arc::new(load(path, cx)?);
+    file.replay(cx);
+    cx.files.insert(path.clone(), Arc::clone(&file));
+    cx.loaded.insert(path.clone());
     Ok(File { path, inner: file })
 }
 
-fn fs_path(path: &Path, cx: &mut Context) -> FsPathBuf {
+/// Reads and analyzes the file of the module without adding it to `cx.files`. The warnings and
+/// the locations of the `use` declarations are kept in the file instead of `cx`.
+fn load(path: &Path, cx: &mut Context) -> Result<FileInner> {
+    let fs_path = fs_path(path, cx)?;
+    let module = format!("{:?}", with_context(path, cx));
+    let content = match cx.config.sources.read(&fs_path) {
+        Ok(content) => normalize_source(content),
+        Err(source) => {
+            let declared_at = declaration_location(path, cx).map(Box::new);
+            return Err(Error::ModuleMissing { module, file: fs_path, declared_at, source });
+        }
+    };
+    let key = cache::key(path, &fs_path, &content, cx);
+    Ok(match cache::load(key, cx) {
+        Some(cached) => FileInner::from_cached(content, cached, cx),
+        None => {
+            let syn_file = match syn::parse_file(&content) {
+                Ok(syn_file) => syn_file,
+                Err(source) => {
+                    let location = parse_error_location(&source, &fs_path, &content);
+                    let source = crate_visibility_error(source, &location);
+                    let declared_at = declaration_location(path, cx).map(Box::new);
+                    return Err(Error::ModuleParse { module, location, declared_at, source });
+                }
+            };
+            let warnings = mem::take(&mut cx.report.warnings);
+            let use_locations = mem::take(&mut cx.use_locations);
+            let mut file = FileInner::parse(content, &syn_file, path, &fs_path, cx);
+            file.warnings = mem::replace(&mut cx.report.warnings, warnings);
+            file.use_locations =
+                mem::replace(&mut cx.use_locations, use_locations).into_iter().collect();
+            cache::store(key, &file.to_cached(cx)?, cx);
+            file
+        }
+    })
+}
+
+/// Loads the files of the modules in parallel. They are added to `cx.files` but not yet used in
+/// the current run, so the warnings are reported when they are used by [`file`], in the same order
+/// as loading them one by one. Errors are also left to be reported by [`file`].
+pub fn load_files(paths: impl IntoIterator<Item = Path>, cx: &mut Context) {
+    let paths =
+        paths.into_iter().filter(|path| !cx.files.contains_key(path)).unique().collect_vec();
+    if paths.is_empty() {
+        return;
+    }
+    log::debug!("loading {} files in parallel", paths.len());
+    let base = cx.fork();
+    let files = paths
+        .into_par_iter()
+        .filter_map(|path| {
+            let file = load(&path, &mut base.fork()).ok()?;
+            Some((path, file))
+        })
+        .collect::<Vec<_>>();
+    for (path, file) in files {
+        cx.files.insert(path, Arc::new(file));
+    }
+}
+
+/// Loads the files of the modules and their descendants in parallel, level by level.
+pub fn load_subtrees(roots: Vec<Path>, cx: &mut Context) {
+    let mut paths = roots;
+    while !paths.is_empty() {
+        load_files(paths.iter().cloned(), cx);
+        paths = paths
+            .iter()
+            .filter_map(|path| Some((path, cx.files.get(path)?)))
+            .flat_map(|(path, file)| {
+                file.child_modules.keys().map(move |&symbol| path.child(symbol))
+            })
+            .collect();
+    }
+}
+
+/// Returns the location of the `mod` item declaring the module, if the parent is already loaded.
+fn declaration_location(path: &Path, cx: &Context) -> Option<Location> {
+    let parent = path.parent()?;
+    let parent_file = cx.files.get(&parent)?;
+    let child_module = parent_file.child_modules.get(&path.symbol()?)?;
+    let parent_fs_path = fs_path(&parent, cx).ok()?;
+    let src = SourceFile { file: &parent_fs_path, content: &parent_file.content };
+    Some(src.locate(child_module.item_mod_span.start))
+}
+
+pub fn parse_error_location(err: &syn::Error, fs_path: &FsPath, content: &str) -> Location {
+    SourceFile { file: fs_path, content }.locate_span(err.span())
+}
+
+/// Replaces the error for an item with the `crate` visibility, which the parser no longer
+/// accepts, with one suggesting `pub(crate)`.
+fn crate_visibility_error(err: syn::Error, location: &Location) -> syn::Error {
+    let before = location.snippet.chars().take(location.column).collect::<String>();
+    match before.trim_end().strip_suffix("crate") {
+        Some(s) if !s.ends_with(|c: char| c.is_alphanumeric() || "_$:".contains(c)) => {
+            let message = "the `crate` visibility is not supported; use `pub(crate)` instead";
+            syn::Error::new(err.span(), message)
+        }
+        _ => err,
+    }
+}
+
+/// Removes the BOM and converts CRLF line endings to LF, so that the spans reported by the parser
+/// match the content.
+pub fn normalize_source(mut content: String) -> String {
+    if content.starts_with('\u{feff}') {
+        content.drain(..'\u{feff}'.len_utf8());
+    }
+    if content.contains('\r') {
+        content = content.replace("\r\n", "\n");
+    }
+    content
+}
+
+pub fn fs_path(path: &Path, cx: &Context) -> Result<FsPathBuf> {
     let mut fs_path = cx.config.crate_src_dir.to_owned();
     if path.is_root() {
         fs_path.push("lib");
     } else {
-        fs_path.extend(path.iter().map(|symbol| cx.interner.resolve(symbol).unwrap()));
+        for symbol in path.iter() {
+            fs_path.push(&*cx.resolve_symbol(symbol)?);
+        }
     }
-    fs_path.with_extension("rs")
+    Ok(fs_path.with_extension("rs"))
+}
+
+/// Returns the path of the file of the module relative to the root directory of the crate.
+pub fn relative_fs_path(path: &Path, cx: &Context) -> Result<String> {
+    let fs_path = fs_path(path, cx)?;
+    let crate_dir = cx.config.crate_src_dir.parent().unwrap_or(&cx.config.crate_src_dir);
+    let fs_path = fs_path.strip_prefix(crate_dir).unwrap_or(&fs_path);
+    Ok(fs_path.iter().map(|s| s.to_string_lossy()).join("/"))
 }
 
 pub struct ChildModule<'a> {
@@ -474,7 +834,7 @@ impl fmt::Debug for WithContext<'_, '_, ChildModuleInner> {
     }
 }
 
-impl fmt::Debug for WithContext<'_, '_, HashMap<Symbol, ChildModuleInner>> {
+impl fmt::Debug for WithContext<'_, '_, IndexMap<Symbol, ChildModuleInner>> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_map()
             .entries(self.inner.iter().map(|(symbol, child_module)| {
@@ -0,0 +1,170 @@
+use crate::{
+    common::*,
+    deps::Deps,
+    file::{file, relative_fs_path},
+    path::Path,
+    resolve::resolve_path,
+};
+use indexmap::IndexSet;
+use serde::Serialize;
+use smallvec::SmallVec;
+use std::{
+    collections::HashSet,
+    io::{self, Write},
+    mem,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GraphFormat {
+    Dot,
+    Json,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum EdgeKind {
+    /// The module declares the child module with `mod`.
+    Mod,
+    /// The module re-exports items of the child module with `pub use`.
+    Reexport,
+    /// A `use` declaration of the module depends on the module.
+    Use,
+}
+
+impl EdgeKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            EdgeKind::Mod => "mod",
+            EdgeKind::Reexport => "reexport",
+            EdgeKind::Use => "use",
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Node {
+    path: String,
+    file: String,
+    bytes: usize,
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Serialize)]
+struct Edge {
+    from: String,
+    to: String,
+    kind: EdgeKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    line: Option<usize>,
+}
+
+#[derive(Serialize)]
+pub struct Graph {
+    nodes: Vec<Node>,
+    edges: Vec<Edge>,
+}
+
+impl Graph {
+    /// Builds the graph of the modules included in the bundle of `deps`.
+    pub fn new(deps: &Deps, cx: &mut Context) -> Result<Self> {
+        let modules = included_modules(deps, cx)?;
+        let mut nodes = vec![];
+        let mut edges = vec![];
+        for path in &modules {
+            let file = file(path, cx)?;
+            let from = format!("{:?}", with_context(path, cx));
+            nodes.push(Node {
+                path: from.clone(),
+                file: relative_fs_path(path, cx)?,
+                bytes: file.content().len(),
+            });
+            for child_module in file.child_modules() {
+                let child_path = child_module.path();
+                if !modules.contains(&child_path) {
+                    continue;
+                }
+                let to = format!("{:?}", with_context(&child_path, cx));
+                edges.push(Edge {
+                    from: from.clone(),
+                    to: to.clone(),
+                    kind: EdgeKind::Mod,
+                    line: None,
+                });
+                if child_module.item_use_span().is_some() {
+                    edges.push(Edge {
+                        from: from.clone(),
+                        to,
+                        kind: EdgeKind::Reexport,
+                        line: None,
+                    });
+                }
+            }
+            for dep in file.deps().iter() {
+                for to in resolve_path_quietly(dep, cx)? {
+                    let to = format!("{:?}", with_context(&to, cx));
+                    let line = file.use_line(dep);
+                    edges.push(Edge { from: from.clone(), to, kind: EdgeKind::Use, line });
+                }
+            }
+        }
+        nodes.sort_by(|x, y| x.path.cmp(&y.path));
+        edges.sort();
+        edges.dedup_by(|x, y| (&x.from, &x.to, x.kind) == (&y.from, &y.to, y.kind));
+        Ok(Graph { nodes, edges })
+    }
+
+    pub fn write<W>(&self, format: GraphFormat, writer: &mut W) -> Result<()>
+    where
+        W: ?Sized + Write,
+    {
+        match format {
+            GraphFormat::Dot => {
+                writeln!(writer, "digraph modules {{")?;
+                for Node { path, file, bytes } in &self.nodes {
+                    writeln!(
+                        writer,
+                        "    {:?} [label={:?}];",
+                        path,
+                        format!("{}\n{}\n{} bytes", path, file, bytes)
+                    )?;
+                }
+                for Edge { from, to, kind, .. } in &self.edges {
+                    writeln!(writer, "    {:?} -> {:?} [label={:?}];", from, to, kind.as_str())?;
+                }
+                writeln!(writer, "}}")?;
+            }
+            GraphFormat::Json => {
+                serde_json::to_writer_pretty(&mut *writer, self).map_err(io::Error::from)?;
+                writeln!(writer)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Resolves `path` again to find the edges, without reporting the diagnostics already reported
+/// while resolving the dependencies.
+fn resolve_path_quietly(path: &Path, cx: &mut Context) -> Result<SmallVec<[Path; 2]>> {
+    let report = mem::take(&mut cx.report);
+    let resolved_paths = resolve_path(path, cx);
+    cx.report = report;
+    resolved_paths
+}
+
+/// Returns the modules included in the bundle, that is, the modules in `deps`, their ancestors
+/// and their descendants.
+fn included_modules(deps: &Deps, cx: &mut Context) -> Result<IndexSet<Path>> {
+    let mut modules = IndexSet::new();
+    for path in deps.iter() {
+        modules.extend(path.strict_ancestors());
+    }
+    let mut visited = HashSet::new();
+    let mut stack = deps.iter().cloned().collect::<Vec<_>>();
+    while let Some(path) = stack.pop() {
+        if visited.insert(path.clone()) {
+            let file = file(&path, cx)?;
+            stack.extend(file.child_modules().map(|child_module| child_module.path()));
+            modules.insert(path);
+        }
+    }
+    Ok(modules)
+}
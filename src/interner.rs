@@ -0,0 +1,63 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct Symbol(u32);
+
+/// A string interner which can be shared by the threads loading files.
+#[derive(Default, Debug)]
+pub struct Interner(RwLock<InternerInner>);
+
+#[derive(Default, Debug)]
+struct InternerInner {
+    symbols: HashMap<Arc<str>, Symbol>,
+    strings: Vec<Arc<str>>,
+}
+
+impl Interner {
+    pub fn get_or_intern(&self, s: impl AsRef<str>) -> Symbol {
+        let s = s.as_ref();
+        if let Some(&symbol) = self.0.read().unwrap_or_else(|err| err.into_inner()).symbols.get(s) {
+            return symbol;
+        }
+        let mut inner = self.0.write().unwrap_or_else(|err| err.into_inner());
+        // another thread may have interned it after the read lock is released
+        if let Some(&symbol) = inner.symbols.get(s) {
+            return symbol;
+        }
+        let symbol = Symbol(inner.strings.len() as u32);
+        let s = Arc::<str>::from(s);
+        inner.strings.push(Arc::clone(&s));
+        inner.symbols.insert(s, symbol);
+        symbol
+    }
+
+    pub fn resolve(&self, Symbol(index): Symbol) -> Option<Arc<str>> {
+        let inner = self.0.read().unwrap_or_else(|err| err.into_inner());
+        inner.strings.get(index as usize).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interner_test() {
+        let interner = Arc::new(Interner::default());
+        let symbols = (0..4)
+            .map(|_| {
+                let interner = Arc::clone(&interner);
+                std::thread::spawn(move || {
+                    ["a", "b", "c"].iter().map(|s| interner.get_or_intern(s)).collect::<Vec<_>>()
+                })
+            })
+            .map(|handle| handle.join().unwrap())
+            .collect::<Vec<_>>();
+        assert!(symbols.iter().all(|s| *s == symbols[0]));
+        assert_eq!(interner.resolve(symbols[0][1]).as_deref(), Some("b"));
+        assert_eq!(interner.resolve(Symbol(3)), None);
+    }
+}
@@ -1,49 +1,198 @@
+mod bundler;
+mod cache;
+mod check;
 mod common;
 mod concat;
 mod content;
 mod deps;
+mod diagnostic;
+mod error;
+mod explain;
 mod file;
+mod graph;
+mod interner;
 mod path;
+mod report;
 mod resolve;
+mod source;
+mod source_map;
 mod span;
+mod test_bundle;
+mod watch;
+
+pub use crate::{
+    bundler::Bundler,
+    check::{CompilerMessage, MessageOrigin},
+    content::{IndentStyle, LineEnding},
+    diagnostic::{Diagnostic, DiagnosticKind, Location},
+    error::{Error, Result},
+    graph::GraphFormat,
+    report::{ModuleReport, RemovedBytes, Report, UnresolvedSymbol},
+    source::{DiskSources, MemorySources, SourceProvider},
+    source_map::{SourceLocation, SourceMap},
+    test_bundle::{test_bundle, TestReport},
+    watch::Watcher,
+};
 
 use crate::{
+    check::check,
     common::*,
     concat::concat_contents,
     deps::{entry_deps, Deps},
+    diagnostic::SourceFile,
+    explain::{write_explanation, Causes},
+    file::{load_subtrees, normalize_source, parse_error_location},
+    graph::Graph,
+    path::Path,
     resolve::resolve_deps,
 };
-use std::{fs, io::Write, path::PathBuf};
+use std::{io::Write, path::PathBuf, sync::Arc};
 
+/// The configuration of the bundling, which is built with [`Bundler`].
 #[derive(Clone, Debug)]
+#[non_exhaustive]
 pub struct Config {
     pub crate_ident: String,
     pub crate_src_dir: PathBuf,
     pub entry_file_path: Option<PathBuf>,
+    /// The content of the entry file, which is read from `entry_file_path` if `None`.
+    pub entry_source: Option<String>,
+    /// Reads the entry file and the files of the modules.
+    pub sources: Arc<dyn SourceProvider>,
     pub remove_test_modules: bool,
     pub remove_doc_comments: bool,
     pub remove_comments: bool,
-    pub indent_spaces: usize,
+    pub indent: IndentStyle,
+    pub tab_width: usize,
+    pub reindent: bool,
+    pub source_map_path: Option<PathBuf>,
+    pub provenance_comments: bool,
+    pub provenance_commit: Option<String>,
+    pub max_blank_lines: Option<usize>,
+    pub trim_trailing_whitespace: bool,
+    pub trim_blank_lines_around_braces: bool,
+    pub line_ending: LineEnding,
+    /// Fail instead of writing the bundle if any warning is emitted.
+    pub deny_warnings: bool,
+    /// Kinds of warnings which are neither reported nor denied.
+    pub allowed_warnings: Vec<DiagnosticKind>,
+    /// The edition of the crate like `2018`, with which the bundle is compiled.
+    pub edition: String,
+    /// Checks that the entry file and the bundle compile with `rustc` before writing the bundle.
+    pub check: bool,
+    /// The directory in which the analyzed files of the library are cached, or `None` to disable
+    /// the cache.
+    pub cache_dir: Option<PathBuf>,
+}
+
+/// Writes the bundle and returns the report of what went into it.
+pub fn bundle<W>(config: Config, writer: &mut W) -> Result<Report>
+where
+    W: ?Sized + Write,
+{
+    bundle_in(&mut Context::from(config), writer)
+}
+
+fn bundle_in<W>(cx: &mut Context, writer: &mut W) -> Result<Report>
+where
+    W: ?Sized + Write,
+{
+    let (deps, _) = resolve(cx)?;
+    let content = concat_contents(&deps, cx)?;
+    deny_warnings(cx)?;
+    if cx.config.check {
+        let entry = read_entry(cx)?;
+        let entry = entry.as_ref().map(|(file, content)| (&**file, &**content));
+        check(entry, &content, cx)?;
+    }
+    if let Some(source_map_path) = &cx.config.source_map_path {
+        let source_map = SourceMap::new(&content, cx)?;
+        source_map.save(source_map_path)?;
+    }
+    match cx.config.line_ending {
+        LineEnding::Lf => write!(writer, "{}", content)?,
+        LineEnding::Crlf => write!(writer, "{}", content.to_string().replace('\n', "\r\n"))?,
+    }
+    Ok(std::mem::take(&mut cx.report))
 }
 
-pub fn bundle<W>(config: Config, writer: &mut W) -> Result<()>
+/// Writes the chain of dependencies through which the entry file depends on `module`, a path
+/// like `crate::a::b`, and returns the warnings.
+pub fn explain<W>(config: Config, module: &str, writer: &mut W) -> Result<Vec<Diagnostic>>
 where
     W: ?Sized + Write,
 {
     let cx = &mut Context::from(config);
-    let deps = match &cx.config.entry_file_path {
-        Some(entry_file_path) => {
-            let entry_content = fs::read_to_string(entry_file_path)?;
-            let entry_syn_file = syn::parse_file(&entry_content)?;
-            let entry_deps = entry_deps(&entry_syn_file, cx);
-            log::info!("entry dependencies collected: {:?}", with_context(&entry_deps, cx));
-            let deps = resolve_deps(entry_deps, cx)?;
-            log::info!("dependencies resolved: {:?}", with_context(&deps, cx));
-            deps
+    if cx.config.entry_file_path.is_none() && cx.config.entry_source.is_none() {
+        return Err(Error::InvalidConfig(
+            "the entry file is required to explain dependencies".to_owned(),
+        ));
+    }
+    let target = Path::parse(module, cx)
+        .ok_or_else(|| Error::InvalidConfig(format!("invalid module path `{}`", module)))?;
+    let (_, causes) = resolve(cx)?;
+    deny_warnings(cx)?;
+    write_explanation(&target, &causes, writer, cx)?;
+    Ok(std::mem::take(&mut cx.report.warnings))
+}
+
+/// Writes the graph of the modules of the crate, or of the modules reachable from the entry file
+/// if it is specified, and returns the warnings.
+pub fn emit_graph<W>(config: Config, format: GraphFormat, writer: &mut W) -> Result<Vec<Diagnostic>>
+where
+    W: ?Sized + Write,
+{
+    let cx = &mut Context::from(config);
+    let (deps, _) = resolve(cx)?;
+    let graph = Graph::new(&deps, cx)?;
+    deny_warnings(cx)?;
+    graph.write(format, writer)?;
+    Ok(std::mem::take(&mut cx.report.warnings))
+}
+
+/// Fails with the warnings if any warnings are emitted while they are denied.
+fn deny_warnings(cx: &mut Context) -> Result<()> {
+    if cx.config.deny_warnings && !cx.report.warnings.is_empty() {
+        return Err(Error::DeniedWarnings(std::mem::take(&mut cx.report.warnings)));
+    }
+    Ok(())
+}
+
+fn resolve(cx: &mut Context) -> Result<(Deps, Causes)> {
+    let (entry_file_path, entry_content) = match read_entry(cx)? {
+        Some(entry) => entry,
+        None => {
+            load_subtrees(vec![Path::default()], cx);
+            return Ok((Deps::all(), Causes::default()));
         }
-        None => Deps::all(),
     };
-    let content = concat_contents(&deps, cx)?;
-    write!(writer, "{}", content)?;
-    Ok(())
+    let entry_syn_file = match syn::parse_file(&entry_content) {
+        Ok(syn_file) => syn_file,
+        Err(source) => {
+            let location = parse_error_location(&source, &entry_file_path, &entry_content);
+            return Err(Error::EntryParse { location, source });
+        }
+    };
+    let src = SourceFile { file: &entry_file_path, content: &entry_content };
+    let (entry_deps, entry_use_lines) = entry_deps(&entry_syn_file, src, cx);
+    log::info!("entry dependencies collected: {:?}", with_context(&entry_deps, cx));
+    let mut causes = Causes::from_entry(&entry_use_lines);
+    let deps = resolve_deps(entry_deps, &mut causes, cx)?;
+    log::info!("dependencies resolved: {:?}", with_context(&deps, cx));
+    Ok((deps, causes))
+}
+
+/// Returns the path and the content of the entry file if it is specified.
+fn read_entry(cx: &Context) -> Result<Option<(PathBuf, String)>> {
+    match (&cx.config.entry_file_path, &cx.config.entry_source) {
+        (entry_file_path, Some(content)) => {
+            let entry_file_path = entry_file_path.clone().unwrap_or_else(|| "<entry>".into());
+            Ok(Some((entry_file_path, normalize_source(content.clone()))))
+        }
+        (Some(entry_file_path), None) => match cx.config.sources.read(entry_file_path) {
+            Ok(content) => Ok(Some((entry_file_path.clone(), normalize_source(content)))),
+            Err(source) => Err(Error::EntryRead { file: entry_file_path.clone(), source }),
+        },
+        (None, None) => Ok(None),
+    }
 }
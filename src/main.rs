@@ -1,10 +1,14 @@
-use cargo_simple_bundler::{bundle, Config};
+use cargo_simple_bundler::{
+    Bundler, DiagnosticKind, GraphFormat, IndentStyle, LineEnding, SourceMap,
+};
 
 use anyhow::{anyhow, Result};
-use itertools::Itertools as _;
 use std::{
+    fs,
     io::{self, prelude::*, BufWriter},
     path::PathBuf,
+    process, thread,
+    time::Duration,
 };
 use structopt::{clap, StructOpt};
 
@@ -36,9 +40,137 @@ enum Opt {
             help = "Enable indentation with the specified number of spaces"
         )]
         indent_spaces: Option<usize>,
+        #[structopt(long, conflicts_with = "NUM", help = "Enable indentation with tabs")]
+        indent_tabs: bool,
+        #[structopt(
+            long,
+            value_name = "NUM",
+            default_value = "4",
+            help = "Number of columns a tab is regarded as wide"
+        )]
+        tab_width: usize,
+        #[structopt(
+            long,
+            help = "Rewrite the indentation of the original lines in the style of the indentation"
+        )]
+        reindent: bool,
+        #[structopt(
+            long,
+            value_name = "PATH",
+            help = "Write a source map of the output to the specified file"
+        )]
+        source_map: Option<PathBuf>,
+        #[structopt(
+            long,
+            help = "Put a comment naming the source file at the start of each module"
+        )]
+        provenance_comments: bool,
+        #[structopt(
+            long,
+            requires = "provenance-comments",
+            help = "Include the git commit of the library checkout in the provenance comments"
+        )]
+        provenance_commit: bool,
+        #[structopt(
+            long,
+            value_name = "NUM",
+            help = "Collapse each run of blank lines into at most the specified number of lines"
+        )]
+        max_blank_lines: Option<usize>,
+        #[structopt(long, help = "Remove trailing whitespace")]
+        trim_trailing_whitespace: bool,
+        #[structopt(long, help = "Remove blank lines at the start and the end of blocks")]
+        trim_blank_lines_around_braces: bool,
+        #[structopt(
+            long,
+            value_name = "LF|CRLF",
+            default_value = "LF",
+            parse(try_from_str = parse_line_ending),
+            help = "Line ending of the output"
+        )]
+        line_ending: LineEnding,
+        #[structopt(
+            long,
+            value_name = "dot|json",
+            parse(try_from_str = parse_graph_format),
+            help = "Write the module graph instead of the bundle. Only the modules reachable from \
+                    the entry file are included if it is specified"
+        )]
+        emit_graph: Option<GraphFormat>,
+        #[structopt(
+            long,
+            value_name = "json",
+            possible_values(&["json"]),
+            help = "Write a report of what went into the bundle to stderr"
+        )]
+        report: Option<String>,
+        #[structopt(long, help = "Fail without writing the bundle if any warning is emitted")]
+        deny_warnings: bool,
+        #[structopt(
+            long = "allow",
+            value_name = "KIND",
+            number_of_values = 1,
+            help = "Neither report nor deny the kind of warnings like `definition-not-found`"
+        )]
+        allowed_warnings: Vec<DiagnosticKind>,
+        #[structopt(
+            long,
+            help = "Check that the entry file and the bundle compile with `rustc` before writing \
+                    the bundle"
+        )]
+        check: bool,
+        #[structopt(
+            long,
+            value_name = "EDITION",
+            help = "Edition with which `--check` and `test` compile [default: the edition of the \
+                    package]"
+        )]
+        edition: Option<String>,
+        #[structopt(
+            short = "o",
+            long,
+            value_name = "PATH",
+            help = "Write the output to the specified file instead of stdout"
+        )]
+        output: Option<PathBuf>,
+        #[structopt(
+            long,
+            requires = "output",
+            conflicts_with = "emit-graph",
+            help = "Bundle again whenever the entry file or the library is modified"
+        )]
+        watch: bool,
+        #[structopt(long, help = "Do not cache the analyzed files of the library")]
+        no_cache: bool,
+        #[structopt(subcommand)]
+        command: Option<Command>,
     },
 }
 
+#[derive(StructOpt)]
+enum Command {
+    #[structopt(about = "Translate a location in the bundled output into the library location")]
+    Map {
+        #[structopt(long, value_name = "PATH", help = "Path to the source map")]
+        source_map: PathBuf,
+        #[structopt(
+            value_name = "FILE:LINE",
+            help = "Location in the file the output is appended to"
+        )]
+        location: String,
+    },
+    #[structopt(about = "Show why the module is included in the bundle")]
+    Explain {
+        #[structopt(value_name = "MODULE", help = "Path to the module like `crate::a::b`")]
+        module: String,
+    },
+    #[structopt(
+        about = "Run the tests of the library in the bundle and show which fail only there. \
+                 Test modules are kept regardless of `--remove-test-modules`"
+    )]
+    Test,
+}
+
 fn main() -> Result<()> {
     let env = env_logger::Env::default().filter_or(env_logger::DEFAULT_FILTER_ENV, "warn");
     env_logger::Builder::from_env(env).format_module_path(false).format_timestamp(None).init();
@@ -50,40 +182,205 @@ fn main() -> Result<()> {
         remove_comments,
         entry_file_path,
         indent_spaces,
+        indent_tabs,
+        tab_width,
+        reindent,
+        source_map,
+        provenance_comments,
+        provenance_commit,
+        max_blank_lines,
+        trim_trailing_whitespace,
+        trim_blank_lines_around_braces,
+        line_ending,
+        emit_graph: graph_format,
+        report: report_format,
+        deny_warnings,
+        allowed_warnings,
+        check,
+        edition,
+        output,
+        watch,
+        no_cache,
+        command,
     } = Opt::from_args();
 
-    let config = {
-        let metadata = {
-            let mut cmd = cargo_metadata::MetadataCommand::new();
-            if let Some(path) = manifest_path {
-                cmd.manifest_path(path);
-            }
-            cmd.exec()?
-        };
-        let package = metadata.root_package().ok_or_else(|| anyhow!("root package not found"))?;
-        let target = package
-            .targets
-            .iter()
-            .filter(|target| {
-                target.name == package.name && target.kind.iter().any(|kind| kind.ends_with("lib"))
-            })
-            .exactly_one()
-            .map_err(|_| anyhow!("target not found or multiple targets found"))?;
-
-        Config {
-            crate_ident: package.name.replace('-', "_"),
-            crate_src_dir: target.src_path.parent().unwrap().to_owned().into(),
-            entry_file_path,
-            remove_test_modules,
-            remove_doc_comments,
-            remove_comments,
-            indent_spaces: indent_spaces.unwrap_or(0),
-        }
-    };
+    if watch && command.is_some() {
+        return Err(anyhow!("`--watch` cannot be used with a subcommand"));
+    }
+    if let Some(Command::Map { source_map, location }) = &command {
+        return map(source_map, location);
+    }
+
+    let mut bundler = Bundler::from_manifest(manifest_path.as_deref())?;
+    bundler
+        .remove_test_modules(remove_test_modules)
+        .remove_doc_comments(remove_doc_comments)
+        .remove_comments(remove_comments)
+        .indent(if indent_tabs {
+            IndentStyle::Tabs
+        } else {
+            IndentStyle::Spaces(indent_spaces.unwrap_or(0))
+        })
+        .tab_width(tab_width)
+        .reindent(reindent)
+        .provenance_comments(provenance_comments)
+        .trim_trailing_whitespace(trim_trailing_whitespace)
+        .trim_blank_lines_around_braces(trim_blank_lines_around_braces)
+        .line_ending(line_ending)
+        .deny_warnings(deny_warnings)
+        .check(check);
+    if let Some(entry_file_path) = entry_file_path {
+        bundler.entry_file(entry_file_path);
+    }
+    if let Some(source_map) = source_map {
+        bundler.source_map(source_map);
+    }
+    if provenance_commit {
+        bundler.provenance_commit(git_commit(&bundler.config().crate_src_dir)?);
+    }
+    if let Some(max_blank_lines) = max_blank_lines {
+        bundler.max_blank_lines(max_blank_lines);
+    }
+    for kind in allowed_warnings {
+        bundler.allow(kind);
+    }
+    if let Some(edition) = edition {
+        bundler.edition(edition);
+    }
+    if no_cache {
+        bundler.cache_dir(None);
+    }
+
+    if let Some(Command::Test) = command {
+        return test(&bundler);
+    }
+
+    if let (true, Some(output)) = (watch, &output) {
+        return watch_files(&bundler, output);
+    }
 
     let stdout = io::stdout();
-    let mut writer = BufWriter::new(stdout.lock());
-    bundle(config, &mut writer)?;
+    let mut buf = vec![];
+    // the file is written only if the bundling succeeds
+    let mut writer: Box<dyn Write> = match output {
+        Some(_) => Box::new(&mut buf),
+        None => Box::new(BufWriter::new(stdout.lock())),
+    };
+    let warnings = match command {
+        Some(Command::Explain { module }) => bundler.explain(&module, &mut writer)?,
+        _ => match graph_format {
+            Some(graph_format) => bundler.emit_graph(graph_format, &mut writer)?,
+            None => {
+                let report = bundler.bundle(&mut writer)?;
+                if report_format.is_some() {
+                    let stderr = io::stderr();
+                    let mut stderr = stderr.lock();
+                    serde_json::to_writer_pretty(&mut stderr, &report)?;
+                    writeln!(stderr)?;
+                }
+                report.warnings
+            }
+        },
+    };
     writer.flush()?;
+    drop(writer);
+    if let Some(output) = &output {
+        fs::write(output, &buf)?;
+    }
+    for warning in &warnings {
+        eprintln!("{}\n", warning);
+    }
+    Ok(())
+}
+
+fn watch_files(bundler: &Bundler, output: &std::path::Path) -> Result<()> {
+    let mut watcher = bundler.watcher();
+    loop {
+        if watcher.poll() {
+            let mut buf = vec![];
+            match watcher.bundle(&mut buf) {
+                Ok(report) => {
+                    fs::write(output, &buf)?;
+                    for warning in &report.warnings {
+                        eprintln!("{}\n", warning);
+                    }
+                    eprintln!("wrote the bundle to `{}`", output.display());
+                }
+                Err(err) => eprintln!("error: {}\n", err),
+            }
+        }
+        thread::sleep(Duration::from_millis(500));
+    }
+}
+
+fn test(bundler: &Bundler) -> Result<()> {
+    let report = bundler.test()?;
+    for name in &report.failed_in_both {
+        println!("test {} ... FAILED (also in the original crate)", name);
+    }
+    for name in &report.failed_only_bundled {
+        println!("test {} ... FAILED (only in the bundle)", name);
+    }
+    println!(
+        "{} passed; {} failed only in the bundle; {} failed also in the original crate",
+        report.passed.len(),
+        report.failed_only_bundled.len(),
+        report.failed_in_both.len(),
+    );
+    if !report.failed_only_bundled.is_empty() {
+        return Err(anyhow!("some tests fail only in the bundle"));
+    }
+    Ok(())
+}
+
+fn parse_line_ending(s: &str) -> Result<LineEnding> {
+    match &*s.to_ascii_lowercase() {
+        "lf" => Ok(LineEnding::Lf),
+        "crlf" => Ok(LineEnding::Crlf),
+        _ => Err(anyhow!("expected `LF` or `CRLF`")),
+    }
+}
+
+fn parse_graph_format(s: &str) -> Result<GraphFormat> {
+    match s {
+        "dot" => Ok(GraphFormat::Dot),
+        "json" => Ok(GraphFormat::Json),
+        _ => Err(anyhow!("expected `dot` or `json`")),
+    }
+}
+
+fn git_commit(dir: &std::path::Path) -> Result<String> {
+    let output = process::Command::new("git")
+        .arg("rev-parse")
+        .arg("--short")
+        .arg("HEAD")
+        .current_dir(dir)
+        .output()?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "failed to get the git commit of `{}`: {}",
+            dir.display(),
+            String::from_utf8_lossy(&output.stderr).trim(),
+        ));
+    }
+    Ok(String::from_utf8(output.stdout)?.trim().to_owned())
+}
+
+fn map(source_map: &std::path::Path, location: &str) -> Result<()> {
+    let (file, line) = location
+        .rsplit_once(':')
+        .and_then(|(file, line)| Some((file, line.parse::<usize>().ok()?)))
+        .ok_or_else(|| anyhow!("invalid location `{}`. expected `FILE:LINE`", location))?;
+    let source_map = SourceMap::load(source_map)?;
+    let file_content = fs::read_to_string(file)?;
+    let source_location = source_map
+        .locate(&file_content, line)
+        .ok_or_else(|| anyhow!("`{}` is not a line from the library", location))?;
+    println!(
+        "{}:{} ({})",
+        source_location.file.display(),
+        source_location.line,
+        source_location.module,
+    );
     Ok(())
 }
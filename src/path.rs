@@ -1,8 +1,9 @@
 use crate::common::*;
+use indexmap::IndexSet;
 use smallvec::SmallVec;
-use std::{collections::HashSet, fmt, iter};
+use std::{fmt, iter};
 
-#[derive(Clone, Default, PartialEq, Eq, Hash)]
+#[derive(Clone, Default, PartialEq, Eq, Hash, Debug)]
 pub struct Path(SmallVec<[Symbol; 4]>);
 
 impl fmt::Debug for WithContext<'_, '_, Path> {
@@ -15,13 +16,39 @@ impl fmt::Debug for WithContext<'_, '_, Path> {
     }
 }
 
-impl fmt::Debug for WithContext<'_, '_, HashSet<Path>> {
+impl fmt::Debug for WithContext<'_, '_, IndexSet<Path>> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_set().entries(self.inner.iter().map(|path| with_context(path, self.cx))).finish()
     }
 }
 
 impl Path {
+    /// Parses a path like `crate::a::b`. The crate name can be used instead of `crate`.
+    pub fn parse(s: &str, cx: &mut Context) -> Option<Self> {
+        let mut segments = s.split("::").map(str::trim);
+        let first = segments.next()?;
+        if first != "crate" && first != cx.config.crate_ident {
+            return None;
+        }
+        let mut path = Path::default();
+        for segment in segments {
+            if segment.is_empty() {
+                return None;
+            }
+            path = path.child(cx.interner.get_or_intern(segment));
+        }
+        Some(path)
+    }
+
+    /// Returns the names of the segments after `crate`.
+    pub fn segments(&self, cx: &Context) -> Result<Vec<String>> {
+        self.iter().map(|symbol| Ok(cx.resolve_symbol(symbol)?.to_string())).collect()
+    }
+
+    pub fn from_segments(segments: &[String], cx: &mut Context) -> Self {
+        Path(segments.iter().map(|segment| cx.interner.get_or_intern(segment)).collect())
+    }
+
     pub fn is_root(&self) -> bool {
         self.0.is_empty()
     }
@@ -0,0 +1,48 @@
+use crate::diagnostic::Diagnostic;
+use serde::{Deserialize, Serialize};
+use std::ops::AddAssign;
+
+/// What went into a bundle.
+#[derive(Clone, Default, Debug, Serialize)]
+pub struct Report {
+    /// Modules whose code is included, in the order of the output.
+    pub included_modules: Vec<ModuleReport>,
+    /// Modules whose declarations are removed from the output.
+    pub removed_modules: Vec<String>,
+    /// Bytes removed from the included modules by each removal option.
+    pub bytes_saved: RemovedBytes,
+    /// Warnings emitted while analyzing the crate.
+    pub warnings: Vec<Diagnostic>,
+    /// Paths whose definitions are not found, which are treated as dependencies on their parents.
+    pub unresolved_symbols: Vec<UnresolvedSymbol>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ModuleReport {
+    pub path: String,
+    pub file: String,
+    /// Bytes of the output that come from the file of the module.
+    pub bytes: usize,
+}
+
+/// Bytes of code removed by each removal option. Whitespace is not counted.
+#[derive(Clone, Copy, Default, Debug, Serialize, Deserialize)]
+pub struct RemovedBytes {
+    pub comments: usize,
+    pub doc_comments: usize,
+    pub test_modules: usize,
+}
+
+impl AddAssign for RemovedBytes {
+    fn add_assign(&mut self, other: Self) {
+        self.comments += other.comments;
+        self.doc_comments += other.doc_comments;
+        self.test_modules += other.test_modules;
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct UnresolvedSymbol {
+    pub path: String,
+    pub fallback: String,
+}
@@ -1,8 +1,16 @@
-use crate::{common::*, deps::Deps, file::file, path::Path};
+use crate::{
+    common::*,
+    deps::Deps,
+    diagnostic::DiagnosticKind,
+    explain::{Causes, UseSite},
+    file::{file, load_files, load_subtrees},
+    path::Path,
+    report::UnresolvedSymbol,
+};
 use smallvec::SmallVec;
 use std::{collections::HashSet, iter};
 
-pub fn resolve_deps(mut deps: Deps, cx: &mut Context) -> Result<Deps> {
+pub fn resolve_deps(mut deps: Deps, causes: &mut Causes, cx: &mut Context) -> Result<Deps> {
     log::debug!("resolving dependencies: {:?}", with_context(&deps, cx));
 
     let mut final_deps = Deps::default();
@@ -10,20 +18,32 @@ pub fn resolve_deps(mut deps: Deps, cx: &mut Context) -> Result<Deps> {
 
     while !deps.is_empty() {
         log::debug!("deps = {:?}", with_context(&deps, cx));
+        load_files(
+            deps.iter().filter(|path| !known_paths.contains(*path)).filter_map(Path::parent),
+            cx,
+        );
         let mut resolved_deps = Deps::default();
         for path in deps.drain() {
             if known_paths.insert(path.clone()) {
-                resolved_deps.extend(resolve_path(&path, cx)?);
+                let resolved_paths = resolve_path(&path, cx)?;
+                for resolved_path in &resolved_paths {
+                    causes.add_resolution(&path, resolved_path);
+                }
+                resolved_deps.extend(resolved_paths);
             }
         }
         log::debug!("resolved_deps = {:?}", with_context(&resolved_deps, cx));
+        load_subtrees(
+            resolved_deps.iter().filter(|path| !final_deps.contains(path)).cloned().collect(),
+            cx,
+        );
         for path in resolved_deps {
             if final_deps.insert(path.clone()) {
-                for path in path.strict_ancestors() {
-                    let file = file(&path, cx)?;
-                    deps.extend(file.deps().iter().cloned());
+                for ancestor in path.strict_ancestors() {
+                    causes.add_ancestor(&path, &ancestor);
+                    collect_file_deps(&ancestor, &mut deps, causes, cx)?;
                 }
-                collect_descendant_deps(&path, &mut deps, cx)?;
+                collect_descendant_deps(&path, &path, &mut deps, causes, cx)?;
             }
         }
     }
@@ -31,26 +51,47 @@ pub fn resolve_deps(mut deps: Deps, cx: &mut Context) -> Result<Deps> {
     Ok(final_deps)
 }
 
-fn collect_descendant_deps(path: &Path, deps: &mut Deps, cx: &mut Context) -> Result<()> {
+fn collect_file_deps(
+    path: &Path,
+    deps: &mut Deps,
+    causes: &mut Causes,
+    cx: &mut Context,
+) -> Result<()> {
+    let file = file(path, cx)?;
+    for dep in file.deps().iter() {
+        let line = file.use_line(dep).unwrap_or_default();
+        causes.add_use_site(dep, UseSite { module: Some(path.clone()), line });
+        deps.insert(dep.clone());
+    }
+    Ok(())
+}
+
+fn collect_descendant_deps(
+    root: &Path,
+    path: &Path,
+    deps: &mut Deps,
+    causes: &mut Causes,
+    cx: &mut Context,
+) -> Result<()> {
+    collect_file_deps(path, deps, causes, cx)?;
     let file = file(path, cx)?;
-    deps.extend(file.deps().iter().cloned());
     for path in file.child_modules().map(|child_module| path.child(child_module.symbol())) {
-        collect_descendant_deps(&path, deps, cx)?;
+        causes.add_descendant(root, &path);
+        collect_descendant_deps(root, &path, deps, causes, cx)?;
     }
     Ok(())
 }
 
-fn resolve_path(path: &Path, cx: &mut Context) -> Result<SmallVec<[Path; 2]>> {
-    let parent = match path.parent() {
-        None => return Ok(iter::once(path.clone()).collect()), // path is root
-        Some(x) => x,
+pub fn resolve_path(path: &Path, cx: &mut Context) -> Result<SmallVec<[Path; 2]>> {
+    let (parent, symbol) = match (path.parent(), path.symbol()) {
+        (Some(parent), Some(symbol)) => (parent, symbol),
+        _ => return Ok(iter::once(path.clone()).collect()), // path is root
     };
     log::debug!("resolving the path: {:?}", with_context(path, cx));
 
     let parent_file = file(&parent, cx)?;
     log::debug!("parent_file = {:?}", with_context(&parent_file, cx));
 
-    let symbol = path.symbol().unwrap();
     let mut resolved_paths = SmallVec::new();
 
     if parent_file.contains_child_module(symbol) {
@@ -73,12 +114,18 @@ fn resolve_path(path: &Path, cx: &mut Context) -> Result<SmallVec<[Path; 2]>> {
     }
 
     if resolved_paths.is_empty() {
-        let ident = cx.interner.resolve(symbol).unwrap();
-        log::warn!(
+        let ident = cx.resolve_symbol(symbol)?;
+        let message = format!(
             "definition of `{}` not found in `{:?}`. treat as a dependency on the parent",
             ident,
             with_context(&parent, cx),
         );
+        let location = cx.use_locations.get(path).cloned();
+        cx.warn(DiagnosticKind::DefinitionNotFound, message, location);
+        cx.report.unresolved_symbols.push(UnresolvedSymbol {
+            path: format!("{:?}", with_context(path, cx)),
+            fallback: format!("{:?}", with_context(&parent, cx)),
+        });
         resolve_path(&parent, cx)
     } else {
         Ok(resolved_paths)
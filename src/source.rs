@@ -0,0 +1,46 @@
+use std::{
+    collections::HashMap,
+    fmt, fs, io,
+    path::{Path as FsPath, PathBuf as FsPathBuf},
+};
+
+/// Provides the contents of the entry file and the files of the modules.
+pub trait SourceProvider: fmt::Debug + Send + Sync {
+    fn read(&self, path: &FsPath) -> io::Result<String>;
+}
+
+/// Reads the files from the disk.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct DiskSources;
+
+impl SourceProvider for DiskSources {
+    fn read(&self, path: &FsPath) -> io::Result<String> {
+        fs::read_to_string(path)
+    }
+}
+
+/// Serves the files from memory, such as unsaved buffers of an editor.
+#[derive(Clone, Default, Debug)]
+pub struct MemorySources {
+    files: HashMap<FsPathBuf, String>,
+}
+
+impl MemorySources {
+    pub fn new() -> Self {
+        MemorySources::default()
+    }
+
+    pub fn insert(&mut self, path: impl Into<FsPathBuf>, content: impl Into<String>) -> &mut Self {
+        self.files.insert(path.into(), content.into());
+        self
+    }
+}
+
+impl SourceProvider for MemorySources {
+    fn read(&self, path: &FsPath) -> io::Result<String> {
+        self.files.get(path).cloned().ok_or_else(|| {
+            let message = format!("`{}` is not in the memory", path.display());
+            io::Error::new(io::ErrorKind::NotFound, message)
+        })
+    }
+}
@@ -0,0 +1,72 @@
+use crate::{common::*, content::Content, file::fs_path, path::Path};
+use serde::{Deserialize, Serialize};
+use std::{fs, io, path::PathBuf as FsPathBuf};
+
+/// The location in the original sources from which a line of the output comes.
+#[derive(Clone, Debug)]
+pub struct Origin {
+    pub path: Path,
+    pub line: usize,
+}
+
+/// Maps each line of the bundled output back to the library file it comes from.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SourceMap {
+    pub crate_ident: String,
+    /// One entry per line of the output. Lines produced by the bundler itself are `None`.
+    pub lines: Vec<Option<SourceLocation>>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct SourceLocation {
+    pub module: String,
+    pub file: FsPathBuf,
+    pub line: usize,
+}
+
+impl SourceMap {
+    pub(crate) fn new(content: &Content, cx: &Context) -> Result<Self> {
+        let lines = content
+            .line_origins()
+            .into_iter()
+            .map(|origin| {
+                origin
+                    .map(|Origin { path, line }| {
+                        Ok(SourceLocation {
+                            module: format!("{:?}", with_context(&path, cx)),
+                            file: fs_path(&path, cx)?,
+                            line,
+                        })
+                    })
+                    .transpose()
+            })
+            .collect::<Result<_>>()?;
+        Ok(SourceMap { crate_ident: cx.config.crate_ident.clone(), lines })
+    }
+
+    pub fn load(path: &std::path::Path) -> Result<Self> {
+        let json = fs::read_to_string(path)
+            .map_err(|source| Error::Read { file: path.to_owned(), source })?;
+        serde_json::from_str(&json)
+            .map_err(|source| Error::SourceMapParse { file: path.to_owned(), source })
+    }
+
+    pub fn save(&self, path: &std::path::Path) -> Result<()> {
+        let json = serde_json::to_string(self).map_err(io::Error::from)?;
+        fs::write(path, json).map_err(Error::write(Some(path.to_owned())))?;
+        Ok(())
+    }
+
+    /// Returns the location of the 1-based `line` of the output.
+    pub fn lookup(&self, line: usize) -> Option<&SourceLocation> {
+        self.lines.get(line.checked_sub(1)?)?.as_ref()
+    }
+
+    /// Returns the location of the 1-based `line` of `file_content`, a file to which the output
+    /// has been appended.
+    pub fn locate(&self, file_content: &str, line: usize) -> Option<&SourceLocation> {
+        let header = format!("mod {} {{", self.crate_ident);
+        let start = file_content.lines().position(|s| s.starts_with(&header))? + 1;
+        self.lookup(line.checked_sub(start)? + 1)
+    }
+}
@@ -1,26 +1,39 @@
-use std::collections::{btree_map, BTreeMap};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{btree_map, BTreeMap, HashMap},
+    iter,
+};
 
-#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Debug)]
+/// A position in a file. Unlike `proc_macro2::LineColumn`, the column counts bytes so that the
+/// content can be sliced with it.
+#[derive(
+    Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Debug, Serialize, Deserialize,
+)]
 pub struct LineColumn {
+    /// 1-based line number.
     pub line: usize,
+    /// 0-based column number, counted in bytes.
     pub column: usize,
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Debug)]
+#[derive(
+    Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Debug, Serialize, Deserialize,
+)]
 pub struct Span {
     pub start: LineColumn, // inclusive
     pub end: LineColumn,   // exclusive
 }
 
-impl From<proc_macro2::Span> for Span {
-    fn from(span: proc_macro2::Span) -> Span {
-        let start = span.start();
-        let end = span.end();
-        Span {
-            start: LineColumn { line: start.line, column: start.column },
-            end: LineColumn { line: end.line, column: end.column },
-        }
-    }
+/// Returns the column counted in bytes of the position of `proc_macro2` in `line`, which is the
+/// text of the line. The columns of `proc_macro2` count characters.
+pub fn byte_column(line: &str, column: usize) -> usize {
+    let mut chars = line.char_indices().skip(column);
+    chars.next().map_or(line.len(), |(i, _)| i)
+}
+
+/// Returns the column counted in characters of `column` in `line`, which is the text of the line.
+pub fn char_column(line: &str, column: usize) -> usize {
+    line.get(..column).map_or(column, |s| s.chars().count())
 }
 
 #[derive(Clone, Default, Debug)]
@@ -30,34 +43,27 @@ fn into_span((&start, &end): (&LineColumn, &LineColumn)) -> Span {
     Span { start, end }
 }
 
+/// Builds the union by sorting the spans once, which is faster than inserting them one by one.
+impl iter::FromIterator<Span> for SpanUnion {
+    fn from_iter<I: IntoIterator<Item = Span>>(iter: I) -> Self {
+        let mut spans = iter.into_iter().collect::<Vec<_>>();
+        spans.sort_unstable();
+        let mut merged = Vec::<(LineColumn, LineColumn)>::with_capacity(spans.len());
+        for span in spans {
+            match merged.last_mut() {
+                Some((_, end)) if span.start <= *end => *end = span.end.max(*end),
+                _ => merged.push((span.start, span.end)),
+            }
+        }
+        SpanUnion(merged.into_iter().collect())
+    }
+}
+
 impl SpanUnion {
     pub fn iter(&self) -> impl Iterator<Item = Span> + '_ {
         self.0.iter().map(into_span)
     }
 
-    pub fn insert(&mut self, mut span: Span) {
-        if let Some(l_boundary) = self.0.range(..span.start).next_back().map(into_span) {
-            if span.start <= l_boundary.end {
-                // l_boundary: [--------]
-                //       span:       [-----]
-                span.start = l_boundary.start;
-                // l_boundary: [--------]
-                //       span: [<----|-----]
-            }
-        }
-        if let Some(r_boundary) = self.0.range(..=span.end).next_back().map(into_span) {
-            // r_boundary:    [--------]
-            //       span: [-----]
-            span.end = span.end.max(r_boundary.end);
-            // r_boundary:    [--------]
-            //       span: [-----------]
-        }
-        while let Some((&inner_start, _)) = self.0.range(span.start..=span.end).next() {
-            self.0.remove(&inner_start);
-        }
-        self.0.insert(span.start, span.end);
-    }
-
     pub fn remove(&mut self, span: Span) {
         if let Some(l_boundary) = self.0.range(..span.start).next_back().map(into_span) {
             if span.start < l_boundary.end {
@@ -92,73 +98,158 @@ impl SpanUnion {
     }
 }
 
-pub fn take_spans<'a>(content: &'a str, spans: &'a SpanUnion) -> TakeSpans<'a> {
-    TakeSpans { prev_end: LineColumn { line: 1, column: 0 }, rest: content, spans: spans.0.iter() }
+/// The byte offsets of the starts of the lines of a file, computed once per file so that a position
+/// is converted to a byte offset in constant time.
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct LineIndex {
+    starts: Vec<usize>,
+    len: usize,
+    /// The column and the length in bytes of each non-ASCII character of each 0-based line
+    /// containing them, with which the columns of `proc_macro2` are converted into bytes.
+    wide_chars: HashMap<usize, Vec<(usize, usize)>>,
+}
+
+impl LineIndex {
+    pub fn new(content: &str) -> Self {
+        let starts = iter::once(0)
+            .chain(content.match_indices('\n').map(|(i, _)| i + 1))
+            .collect::<Vec<_>>();
+        let mut wide_chars = HashMap::new();
+        for (i, &start) in starts.iter().enumerate() {
+            let end = starts.get(i + 1).map_or(content.len(), |next| next - 1);
+            let line = &content[start..end];
+            if !line.is_ascii() {
+                let chars = line.chars().enumerate().filter(|(_, c)| !c.is_ascii());
+                wide_chars.insert(i, chars.map(|(column, c)| (column, c.len_utf8())).collect());
+            }
+        }
+        LineIndex { starts, len: content.len(), wide_chars }
+    }
+
+    /// Returns the number of the lines. Like [`str::lines`], the empty line after the last line
+    /// break is not counted.
+    pub fn line_count(&self) -> usize {
+        self.starts.len() - (self.starts.last() == Some(&self.len)) as usize
+    }
+
+    /// Returns the length of the 1-based `line` in bytes, excluding the line break.
+    pub fn line_len(&self, line: usize) -> Option<usize> {
+        let start = *self.starts.get(line.checked_sub(1)?)?;
+        let end = self.starts.get(line).map_or(self.len, |next| next - 1);
+        Some(end - start)
+    }
+
+    /// Returns the byte offset of `pos`, or `None` if it is out of the content.
+    pub fn offset(&self, pos: LineColumn) -> Option<usize> {
+        if pos.column > self.line_len(pos.line)? {
+            return None;
+        }
+        Some(self.starts[pos.line - 1] + pos.column)
+    }
+
+    /// Converts the position of `proc_macro2` in the file.
+    pub fn position(
+        &self,
+        proc_macro2::LineColumn { line, column }: proc_macro2::LineColumn,
+    ) -> LineColumn {
+        let wide_chars = line
+            .checked_sub(1)
+            .and_then(|i| self.wide_chars.get(&i))
+            .map_or(&[][..], Vec::as_slice);
+        let n = wide_chars.partition_point(|&(c, _)| c < column);
+        let extra_bytes = wide_chars[..n].iter().map(|&(_, len)| len - 1).sum::<usize>();
+        LineColumn { line, column: column + extra_bytes }
+    }
+
+    /// Converts the span of `proc_macro2` in the file.
+    pub fn span(&self, span: proc_macro2::Span) -> Span {
+        Span { start: self.position(span.start()), end: self.position(span.end()) }
+    }
+}
+
+/// Returns the text of `span` in `content`.
+pub fn span_str<'a>(content: &'a str, line_index: &LineIndex, span: Span) -> &'a str {
+    match (line_index.offset(span.start), line_index.offset(span.end)) {
+        (Some(start), Some(end)) => content.get(start..end).unwrap_or_default(),
+        _ => "",
+    }
+}
+
+/// Returns the number of the bytes of `s` except whitespace.
+pub fn count_code_bytes(s: &str) -> usize {
+    s.bytes().filter(|b| !b.is_ascii_whitespace()).count()
+}
+
+pub fn take_spans<'a>(
+    content: &'a str,
+    line_index: &'a LineIndex,
+    spans: &'a SpanUnion,
+) -> TakeSpans<'a> {
+    TakeSpans {
+        prev_end: LineColumn { line: 1, column: 0 },
+        prev_end_offset: 0,
+        content,
+        line_index,
+        spans: spans.0.iter(),
+    }
 }
 
 #[derive(Debug)]
 pub struct TakeSpans<'a> {
     prev_end: LineColumn,
-    rest: &'a str,
+    prev_end_offset: usize,
+    content: &'a str,
+    line_index: &'a LineIndex,
     spans: btree_map::Iter<'a, LineColumn, LineColumn>,
 }
 
-impl<'a> TakeSpans<'a> {
-    fn nth_line_start(&self, n: usize) -> usize {
-        let mut start = 0;
-        for _ in 0..n {
-            start += self.rest[start..].find('\n').unwrap() + 1;
-        }
-        start
-    }
-}
-
 #[derive(Debug)]
 pub struct TakeSpansItem<'a> {
     pub span: Span,
+    /// The byte offset of the start of the span.
+    pub offset: usize,
     pub line_offset: usize,
     pub column_offset: usize,
     pub content: &'a str,
 }
 
+/// Yields `Err(span)` if the span is out of the content, and stops there.
 impl<'a> Iterator for TakeSpans<'a> {
-    type Item = TakeSpansItem<'a>;
+    type Item = Result<TakeSpansItem<'a>, Span>;
 
     fn next(&mut self) -> Option<Self::Item> {
         let span = into_span(self.spans.next()?);
+        let item = self.take(span).ok_or(span);
+        if item.is_err() {
+            self.spans = Default::default();
+        }
+        Some(item)
+    }
+}
 
+impl<'a> TakeSpans<'a> {
+    fn take(&mut self, span: Span) -> Option<TakeSpansItem<'a>> {
         let line_offset = span.start.line - self.prev_end.line;
-        let column_offset =
-            span.start.column - if line_offset == 0 { self.prev_end.column } else { 0 };
-
-        let start_idx = if line_offset == 0 {
-            span.start.column - self.prev_end.column
-        } else {
-            self.nth_line_start(line_offset) + span.start.column
-        };
-        self.rest = &self.rest[start_idx..];
-
-        let end_idx = if span.start.line == span.end.line {
-            span.end.column - span.start.column
-        } else {
-            self.nth_line_start(span.end.line - span.start.line) + span.end.column
-        };
-        let (content, rest) = self.rest.split_at(end_idx);
-        self.rest = rest;
+        let offset = self.line_index.offset(span.start)?;
+        let end = self.line_index.offset(span.end)?;
+        // the width of the skipped part of the line, in characters
+        let skipped_start =
+            if line_offset == 0 { self.prev_end_offset } else { offset - span.start.column };
+        let column_offset = self.content.get(skipped_start..offset)?.chars().count();
+        let content = self.content.get(offset..end)?;
         self.prev_end = span.end;
-
-        Some(TakeSpansItem { span, line_offset, column_offset, content })
+        self.prev_end_offset = end;
+        Some(TakeSpansItem { span, offset, line_offset, column_offset, content })
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use itertools::Itertools as _;
 
     #[test]
     fn span_union_test() {
-        let mut span_union = SpanUnion::default();
-
         // span0: [-----]
         // span1:            [-----]
         // span2:    [-------]
@@ -168,21 +259,21 @@ mod tests {
             start: LineColumn { line: 1, column: 2 },
             end: LineColumn { line: 1, column: 4 },
         };
-        span_union.insert(span0);
+        let mut span_union = iter::once(span0).collect::<SpanUnion>();
         assert_eq!(span_union.iter().collect::<Vec<_>>(), [span0]);
 
         let span1 = Span {
             start: LineColumn { line: 2, column: 1 },
             end: LineColumn { line: 2, column: 3 },
         };
-        span_union.insert(span1);
+        span_union = [span1, span0].iter().copied().collect();
         assert_eq!(span_union.iter().collect::<Vec<_>>(), [span0, span1]);
 
         let span2 = Span {
             start: LineColumn { line: 1, column: 3 },
             end: LineColumn { line: 2, column: 1 },
         };
-        span_union.insert(span2);
+        span_union = [span0, span1, span2].iter().copied().collect();
         assert_eq!(
             span_union.iter().collect::<Vec<_>>(),
             [Span { start: span0.start, end: span1.end }]
@@ -204,8 +295,7 @@ mod tests {
 
     #[test]
     fn take_spans_test() {
-        let mut span_union = SpanUnion::default();
-        let spans = vec![
+        let spans = [
             Span {
                 start: LineColumn { line: 1, column: 3 },
                 end: LineColumn { line: 2, column: 2 },
@@ -219,30 +309,32 @@ mod tests {
                 end: LineColumn { line: 3, column: 6 },
             },
         ];
-        for &span in &spans {
-            span_union.insert(span);
-        }
+        let span_union = spans.iter().copied().collect();
 
         let content = "012345\n012345\n012345";
-        let mut iter = take_spans(content, &span_union);
+        let line_index = LineIndex::new(content);
+        let mut iter = take_spans(content, &line_index, &span_union);
 
         {
-            let item = iter.next().unwrap();
+            let item = iter.next().unwrap().unwrap();
             assert_eq!(item.span, spans[0]);
+            assert_eq!(item.offset, 3);
             assert_eq!(item.line_offset, 0);
             assert_eq!(item.column_offset, 3);
             assert_eq!(item.content, "345\n01");
         }
         {
-            let item = iter.next().unwrap();
+            let item = iter.next().unwrap().unwrap();
             assert_eq!(item.span, spans[1]);
+            assert_eq!(item.offset, 11);
             assert_eq!(item.line_offset, 0);
             assert_eq!(item.column_offset, 2);
             assert_eq!(item.content, "4");
         }
         {
-            let item = iter.next().unwrap();
+            let item = iter.next().unwrap().unwrap();
             assert_eq!(item.span, spans[2]);
+            assert_eq!(item.offset, 15);
             assert_eq!(item.line_offset, 1);
             assert_eq!(item.column_offset, 1);
             assert_eq!(item.content, "12345");
@@ -250,4 +342,70 @@ mod tests {
 
         assert!(iter.next().is_none());
     }
+
+    #[test]
+    fn span_str_test() {
+        let content = "fn f() {\n    g(); // h\n}\n";
+        let line_index = LineIndex::new(content);
+        assert_eq!(line_index.starts, vec![0, 9, 23, 25]);
+        assert_eq!(line_index.line_count(), 3);
+        assert_eq!(
+            (1..=4).map(|line| line_index.line_len(line)).collect::<Vec<_>>(),
+            [Some(8), Some(13), Some(1), Some(0)]
+        );
+        let span = |start: (usize, usize), end: (usize, usize)| Span {
+            start: LineColumn { line: start.0, column: start.1 },
+            end: LineColumn { line: end.0, column: end.1 },
+        };
+        assert_eq!(span_str(content, &line_index, span((2, 4), (2, 8))), "g();");
+        assert_eq!(span_str(content, &line_index, span((2, 8), (3, 0))), " // h\n");
+        assert_eq!(span_str(content, &line_index, span((2, 4), (9, 0))), "");
+        assert_eq!(span_str(content, &line_index, span((1, 4), (1, 10))), "");
+        assert_eq!(LineIndex::new("").line_count(), 0);
+        assert_eq!(LineIndex::new("a").line_count(), 1);
+        assert_eq!(count_code_bytes(" // h\n"), 3);
+    }
+
+    #[test]
+    fn multibyte_test() {
+        let content = "// 日本語\nlet s = \"αβ\"; crate::f();\n";
+        let line_index = LineIndex::new(content);
+        assert_eq!(line_index.line_len(1), Some(12));
+        assert_eq!(line_index.line_len(2), Some(27));
+
+        // the spans of the tokens counted in characters slice the content correctly in bytes
+        let tokens = content.parse::<proc_macro2::TokenStream>().unwrap().into_iter().collect_vec();
+        let spans = tokens.iter().map(|token| line_index.span(token.span())).collect_vec();
+        let strs = spans.iter().map(|&span| span_str(content, &line_index, span)).collect_vec();
+        assert_eq!(strs, ["let", "s", "=", "\"αβ\"", ";", "crate", ":", ":", "f", "()", ";"]);
+        let pos = |line, column| LineColumn { line, column };
+        assert_eq!(spans[5], Span { start: pos(2, 16), end: pos(2, 21) });
+        assert_eq!(char_column("let s = \"αβ\"; crate::f();", 16), 14);
+        assert_eq!(byte_column("αβ", 1), 2);
+
+        let span_union =
+            [Span { start: pos(1, 3), end: pos(1, 12) }, spans[3]].iter().copied().collect();
+        let chunks = take_spans(content, &line_index, &span_union)
+            .map(|item| {
+                let item = item.unwrap();
+                (item.offset, item.line_offset, item.column_offset, item.content)
+            })
+            .collect_vec();
+        // the skipped columns count characters
+        assert_eq!(chunks, [(3, 0, 3, "日本語"), (21, 1, 8, "\"αβ\"")]);
+    }
+
+    #[test]
+    fn take_spans_out_of_content_test() {
+        let span = Span {
+            start: LineColumn { line: 3, column: 0 },
+            end: LineColumn { line: 3, column: 1 },
+        };
+        let span_union = iter::once(span).collect();
+
+        let line_index = LineIndex::new("a\n");
+        let mut iter = take_spans("a\n", &line_index, &span_union);
+        assert_eq!(iter.next().unwrap().unwrap_err(), span);
+        assert!(iter.next().is_none());
+    }
 }
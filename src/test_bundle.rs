@@ -0,0 +1,202 @@
+use crate::{bundle, check::scratch_dir, common::*, Config, LineEnding};
+use cargo_metadata::{Dependency, DependencyKind};
+use serde::Serialize;
+use std::{
+    collections::BTreeMap, env, fmt::Write as _, fs, path::Path as FsPath, process::Command,
+};
+
+/// The results of the tests of the library in the original crate and in the bundle.
+#[derive(Clone, Default, Debug, Serialize)]
+pub struct TestReport {
+    /// Tests which pass in the bundle.
+    pub passed: Vec<String>,
+    /// Tests which pass in the original crate but fail in the bundle.
+    pub failed_only_bundled: Vec<String>,
+    /// Tests which fail both in the original crate and in the bundle.
+    pub failed_in_both: Vec<String>,
+}
+
+/// Bundles the library with its test modules into the `lib.rs` of a scratch crate, and runs
+/// `cargo test --offline` on both of the scratch crate and the original crate.
+pub fn test_bundle(mut config: Config) -> Result<TestReport> {
+    config.remove_test_modules = false;
+    config.source_map_path = None;
+    config.check = false;
+    config.line_ending = LineEnding::Lf;
+    let crate_ident = config.crate_ident.clone();
+    let edition = config.edition.clone();
+    let crate_src_dir = config.crate_src_dir.clone();
+    let tables = manifest_tables(&crate_src_dir)?;
+    let mut lib = vec![];
+    bundle(config, &mut lib)?;
+
+    let dir = scratch_dir("test")?;
+    let results = write_scratch_crate(&dir, &crate_ident, &edition, &tables, &lib).and_then(|()| {
+        // the original crate is built in its own target directory to reuse the previous builds
+        let original = run_tests(&crate_src_dir)?;
+        let bundled = run_tests(&dir)?;
+        Ok((original, bundled))
+    });
+    let _ = fs::remove_dir_all(&dir);
+    let (original, bundled) = results?;
+
+    let mut report = TestReport::default();
+    let prefix = format!("{}::", crate_ident);
+    for (name, passed) in bundled {
+        // the paths of the tests in the bundle are prefixed with the module of the library
+        let name = name.strip_prefix(&prefix).map(str::to_owned).unwrap_or(name);
+        if passed {
+            report.passed.push(name);
+        } else if original.get(&name) == Some(&false) {
+            report.failed_in_both.push(name);
+        } else {
+            report.failed_only_bundled.push(name);
+        }
+    }
+    Ok(report)
+}
+
+/// Returns the tables of the dependencies and the features of the package whose library is in
+/// `crate_src_dir`, so that the bundle is built with the same crates.
+fn manifest_tables(crate_src_dir: &FsPath) -> Result<String> {
+    let metadata = cargo_metadata::MetadataCommand::new()
+        .current_dir(crate_src_dir)
+        .no_deps()
+        .exec()
+        .map_err(|err| Error::Metadata(err.to_string()))?;
+    let crate_src_dir =
+        fs::canonicalize(crate_src_dir).unwrap_or_else(|_| crate_src_dir.to_owned());
+    let package = metadata
+        .packages
+        .iter()
+        .find(|package| {
+            package.targets.iter().any(|target| {
+                target.kind.iter().any(|kind| kind.ends_with("lib"))
+                    && target.src_path.parent().and_then(|dir| fs::canonicalize(dir).ok())
+                        == Some(crate_src_dir.clone())
+            })
+        })
+        .ok_or_else(|| {
+            let dir = crate_src_dir.display();
+            Error::Metadata(format!("package of the library in `{}` not found", dir))
+        })?;
+
+    let mut tables = BTreeMap::<String, Vec<String>>::new();
+    for dependency in &package.dependencies {
+        let kind = match dependency.kind {
+            DependencyKind::Normal => "dependencies",
+            DependencyKind::Development => "dev-dependencies",
+            _ => continue,
+        };
+        let table = match &dependency.target {
+            Some(target) => format!("target.{}.{}", toml_string(&target.to_string()), kind),
+            None => kind.to_owned(),
+        };
+        tables.entry(table).or_default().push(dependency_entry(dependency));
+    }
+    if !package.features.is_empty() {
+        let features = package.features.iter().map(|(feature, enables)| {
+            let enables = enables.iter().map(|s| toml_string(s)).join(", ");
+            format!("{} = [{}]", toml_string(feature), enables)
+        });
+        tables.insert("features".to_owned(), features.sorted().collect());
+    }
+
+    let mut s = String::new();
+    for (table, entries) in tables {
+        write!(s, "\n[{}]\n", table).unwrap();
+        for entry in entries {
+            writeln!(s, "{}", entry).unwrap();
+        }
+    }
+    Ok(s)
+}
+
+fn dependency_entry(dependency: &Dependency) -> String {
+    let mut fields = vec![format!("version = {}", toml_string(&dependency.req.to_string()))];
+    if dependency.rename.is_some() {
+        fields.push(format!("package = {}", toml_string(&dependency.name)));
+    }
+    if let Some(path) = &dependency.path {
+        fields.push(format!("path = {}", toml_string(path.as_str())));
+    }
+    if let Some(git) = dependency.source.as_deref().and_then(|source| source.strip_prefix("git+")) {
+        let git = git.split('#').next().unwrap_or(git);
+        let (url, query) = git.split_once('?').unwrap_or((git, ""));
+        fields.push(format!("git = {}", toml_string(url)));
+        for (key, value) in query.split('&').filter_map(|pair| pair.split_once('=')) {
+            if let "branch" | "tag" | "rev" = key {
+                fields.push(format!("{} = {}", key, toml_string(value)));
+            }
+        }
+    }
+    if let Some(registry) = &dependency.registry {
+        fields.push(format!("registry-index = {}", toml_string(registry)));
+    }
+    if !dependency.uses_default_features {
+        fields.push("default-features = false".to_owned());
+    }
+    if !dependency.features.is_empty() {
+        let features = dependency.features.iter().map(|s| toml_string(s)).join(", ");
+        fields.push(format!("features = [{}]", features));
+    }
+    if dependency.optional {
+        fields.push("optional = true".to_owned());
+    }
+    let key = dependency.rename.as_deref().unwrap_or(&dependency.name);
+    format!("{} = {{ {} }}", toml_string(key), fields.join(", "))
+}
+
+/// Quotes `s` as a basic string of TOML, whose escapes are compatible with the strings of JSON.
+fn toml_string(s: &str) -> String {
+    serde_json::to_string(s).unwrap()
+}
+
+fn write_scratch_crate(
+    dir: &FsPath,
+    crate_ident: &str,
+    edition: &str,
+    tables: &str,
+    lib: &[u8],
+) -> Result<()> {
+    let manifest = format!(
+        "[package]\nname = \"{}_bundled\"\nversion = \"0.0.0\"\nedition = \"{}\"\n\n[workspace]\n{}",
+        crate_ident, edition, tables,
+    );
+    let files = [(dir.join("Cargo.toml"), manifest.as_bytes()), (dir.join("src/lib.rs"), lib)];
+    fs::create_dir_all(dir.join("src")).map_err(Error::write(Some(dir.join("src"))))?;
+    for (file, content) in &files {
+        fs::write(file, content).map_err(Error::write(Some(file.clone())))?;
+    }
+    Ok(())
+}
+
+/// Runs the tests of the library target of the package in `dir`, and returns whether each test
+/// passes. Ignored tests are omitted.
+fn run_tests(dir: &FsPath) -> Result<BTreeMap<String, bool>> {
+    let output = Command::new(env::var_os("CARGO").unwrap_or_else(|| "cargo".into()))
+        // one at a time so that the name of a test is printed before the test aborts the binary
+        .args(["test", "--offline", "--lib", "--no-fail-fast", "--", "--test-threads=1"])
+        .current_dir(dir)
+        .output()
+        .map_err(Error::Cargo)?;
+    let results = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let (name, result) = line.strip_prefix("test ")?.rsplit_once(" ...")?;
+            match result.trim() {
+                "ok" => Some((name.to_owned(), true)),
+                // the test aborted the binary before its result is printed
+                "FAILED" | "" => Some((name.to_owned(), false)),
+                _ => None,
+            }
+        })
+        .collect::<BTreeMap<_, _>>();
+    if !output.status.success() && results.values().all(|&passed| passed) {
+        return Err(Error::TestBuild {
+            dir: dir.to_owned(),
+            message: String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+        });
+    }
+    Ok(results)
+}
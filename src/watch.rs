@@ -0,0 +1,91 @@
+use crate::{bundle_in, common::*, file::fs_path, Config, Report};
+use std::{
+    collections::HashMap,
+    fs,
+    io::Write,
+    mem,
+    path::{Path as FsPath, PathBuf as FsPathBuf},
+    time::SystemTime,
+};
+
+/// Bundles again whenever the entry file or a file of the library used in the last bundling is
+/// modified, reusing the files which are not modified. The files are watched by polling their
+/// modification times on the disk.
+pub struct Watcher {
+    cx: Context,
+    /// The modification time of each watched file, which is `None` if the file does not exist.
+    mtimes: HashMap<FsPathBuf, Option<SystemTime>>,
+    /// Whether nothing has been bundled yet.
+    fresh: bool,
+}
+
+impl Watcher {
+    pub fn new(config: Config) -> Self {
+        Watcher { cx: Context::from(config), mtimes: HashMap::new(), fresh: true }
+    }
+
+    /// Returns whether any watched file has been created, modified or removed since the last call,
+    /// which is always `true` for the first call. The modified files are parsed again in the next
+    /// bundling.
+    pub fn poll(&mut self) -> bool {
+        let fresh = mem::take(&mut self.fresh);
+        let mut modified = vec![];
+        for (file, mtime) in &mut self.mtimes {
+            let current = modified_time(file);
+            if current != *mtime {
+                *mtime = current;
+                modified.push(file.clone());
+            }
+        }
+        if modified.is_empty() {
+            return fresh;
+        }
+        log::info!("modified files: {:?}", modified);
+        let cx = &self.cx;
+        let stale = cx
+            .files
+            .keys()
+            .filter(|path| fs_path(path, cx).map_or(true, |file| modified.contains(&file)))
+            .cloned()
+            .collect::<Vec<_>>();
+        for path in stale {
+            self.cx.files.remove(&path);
+        }
+        true
+    }
+
+    /// Bundles, and watches the files used in it. See [`bundle`](crate::bundle).
+    pub fn bundle<W>(&mut self, writer: &mut W) -> Result<Report>
+    where
+        W: ?Sized + Write,
+    {
+        let started = SystemTime::now();
+        self.cx.start_run();
+        let result = bundle_in(&mut self.cx, writer);
+
+        let cx = &self.cx;
+        let mut files =
+            cx.files.keys().filter_map(|path| fs_path(path, cx).ok()).collect::<Vec<_>>();
+        if let (Some(file), None) = (&cx.config.entry_file_path, &cx.config.entry_source) {
+            files.push(file.clone());
+        }
+        // the file is not cached but should be watched to be fixed
+        match &result {
+            Err(Error::ModuleMissing { file, .. }) => files.push(file.clone()),
+            Err(Error::ModuleParse { location, .. }) => files.push(location.file.clone()),
+            _ => {}
+        }
+        for file in files {
+            // a file modified during the bundling may have been read before the modification, so
+            // it is regarded as modified in the next poll
+            self.mtimes
+                .entry(file)
+                .or_insert_with_key(|file| modified_time(file).filter(|&mtime| mtime < started));
+        }
+        result
+    }
+}
+
+fn modified_time(file: &FsPath) -> Option<SystemTime> {
+    fs::metadata(file).and_then(|metadata| metadata.modified()).ok()
+}
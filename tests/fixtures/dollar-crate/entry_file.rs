@@ -0,0 +1,5 @@
+use library::logger::*;
+
+fn main() {
+    log!("{}", 1);
+}
@@ -0,0 +1,10 @@
+// `$crate` paths in macros are not tracked, so the dependency is declared explicitly
+#[allow(unused_imports)]
+use crate::output::write;
+
+#[macro_export]
+macro_rules! log {
+    ($($arg:tt)*) => {
+        $crate::output::write(&format!($($arg)*))
+    };
+}
@@ -0,0 +1,3 @@
+pub fn write(message: &str) {
+    eprintln!("[log] {}", message);
+}
@@ -0,0 +1,5 @@
+use library::calc::*;
+
+fn main() {
+    println!("{}", sum(&[1, 2, 3]));
+}
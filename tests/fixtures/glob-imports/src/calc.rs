@@ -0,0 +1,5 @@
+use crate::ops::*;
+
+pub fn sum(values: &[i64]) -> i64 {
+    values.iter().fold(ZERO, |acc, &value| add(acc, value))
+}
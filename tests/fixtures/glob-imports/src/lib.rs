@@ -0,0 +1,3 @@
+pub mod calc;
+pub mod ops;
+pub mod unused;
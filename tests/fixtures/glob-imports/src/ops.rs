@@ -0,0 +1,5 @@
+pub const ZERO: i64 = 0;
+
+pub fn add(a: i64, b: i64) -> i64 {
+    a + b
+}
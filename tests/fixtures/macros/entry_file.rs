@@ -0,0 +1,6 @@
+use library::macros::*;
+use library::table::Table;
+
+fn main() {
+    println!("{}", square!(Table::SIZE));
+}
@@ -0,0 +1,3 @@
+pub mod macros;
+pub mod table;
+pub mod unused;
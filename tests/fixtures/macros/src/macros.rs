@@ -0,0 +1,7 @@
+/// Squares the expression.
+#[macro_export]
+macro_rules! square {
+    ($e:expr) => {
+        $e * $e
+    };
+}
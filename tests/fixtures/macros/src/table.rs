@@ -0,0 +1,23 @@
+macro_rules! def {
+    ($name:ident, $size:expr) => {
+        pub struct $name;
+
+        impl $name {
+            pub const SIZE: usize = $size;
+        }
+    };
+}
+
+def!(Table, 3);
+
+/// A dummy declaration so that `Table` is recognized.
+#[cfg(any())]
+pub struct Table;
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn size() {
+        assert_eq!(super::Table::SIZE, 3);
+    }
+}
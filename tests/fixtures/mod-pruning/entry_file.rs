@@ -0,0 +1,5 @@
+use library::graph::tree::Tree;
+
+fn main() {
+    println!("{}", Tree::new(3).len());
+}
@@ -0,0 +1,3 @@
+pub mod dijkstra;
+pub mod tree;
+pub mod union_find;
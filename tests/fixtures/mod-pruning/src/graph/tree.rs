@@ -0,0 +1,13 @@
+use super::union_find::UnionFind;
+
+pub struct Tree(UnionFind);
+
+impl Tree {
+    pub fn new(n: usize) -> Self {
+        Tree(UnionFind::new(n))
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+}
@@ -0,0 +1,11 @@
+pub struct UnionFind(Vec<usize>);
+
+impl UnionFind {
+    pub fn new(n: usize) -> Self {
+        UnionFind((0..n).collect())
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+}
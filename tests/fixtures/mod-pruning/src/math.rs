@@ -0,0 +1 @@
+pub const INF: u64 = u64::MAX;
@@ -0,0 +1,5 @@
+use library::{Point, Shape};
+
+fn main() {
+    println!("{:?}", Shape::new(Point::origin()));
+}
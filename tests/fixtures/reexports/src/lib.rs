@@ -0,0 +1,7 @@
+pub use self::point::*;
+pub use self::shape::Shape;
+pub use self::unused::*;
+
+mod point;
+mod shape;
+mod unused;
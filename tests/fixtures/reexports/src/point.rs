@@ -0,0 +1,8 @@
+#[derive(Clone, Copy, Debug)]
+pub struct Point(pub i64, pub i64);
+
+impl Point {
+    pub fn origin() -> Self {
+        Point(0, 0)
+    }
+}
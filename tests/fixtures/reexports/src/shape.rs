@@ -0,0 +1,12 @@
+use crate::Point;
+
+#[derive(Debug)]
+pub struct Shape {
+    pub center: Point,
+}
+
+impl Shape {
+    pub fn new(center: Point) -> Self {
+        Shape { center }
+    }
+}
@@ -0,0 +1,5 @@
+use library::outer::inner::deepest::Deepest;
+
+fn main() {
+    println!("{}", Deepest::value());
+}
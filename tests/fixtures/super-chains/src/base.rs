@@ -0,0 +1 @@
+pub const BASE: u32 = 40;
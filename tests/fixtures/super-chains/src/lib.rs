@@ -0,0 +1,3 @@
+pub mod base;
+pub mod outer;
+pub mod sibling;
@@ -0,0 +1,5 @@
+use super::super::sibling::SIBLING;
+
+pub fn sibling() -> u32 {
+    SIBLING
+}
@@ -0,0 +1,10 @@
+use super::super::super::base::BASE;
+use super::super::helper;
+
+pub struct Deepest;
+
+impl Deepest {
+    pub fn value() -> u32 {
+        BASE + helper::sibling()
+    }
+}
@@ -0,0 +1 @@
+pub const SIBLING: u32 = 2;
@@ -0,0 +1,5 @@
+use library::items::{Known, Unknown};
+
+fn main() {
+    let _ = (Known, Unknown);
+}
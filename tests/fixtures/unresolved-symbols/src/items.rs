@@ -0,0 +1,4 @@
+use crate::other::Missing;
+
+pub struct Known;
+pub use Missing as Unknown;
@@ -1,5 +1,16 @@
-use cargo_simple_bundler::{bundle, Config};
-use std::{fs, path::Path};
+use cargo_simple_bundler::{
+    Bundler, DiagnosticKind, Error, GraphFormat, IndentStyle, LineEnding, MemorySources,
+    MessageOrigin, SourceMap, SourceProvider,
+};
+use serde::Deserialize;
+use std::{
+    env,
+    fmt::Write as _,
+    fs, io,
+    path::Path,
+    process,
+    sync::atomic::{AtomicBool, Ordering},
+};
 
 const MANIFEST_DIR: &str = env!("CARGO_MANIFEST_DIR");
 
@@ -8,18 +19,25 @@ fn expected_output(name: &str) -> String {
     fs::read_to_string(path).unwrap()
 }
 
+/// Returns the bundler of the crate `library` in `crate_src_dir`, without the entry file.
+fn library(crate_src_dir: &Path) -> Bundler {
+    let mut bundler = Bundler::new("library", crate_src_dir);
+    bundler.indent(IndentStyle::Spaces(4));
+    bundler
+}
+
+fn bundler(remove_doc_comments: bool, remove_test_modules: bool) -> Bundler {
+    let mut bundler = library(&Path::new(MANIFEST_DIR).join("tests/fixtures/library/src"));
+    bundler
+        .entry_file(Path::new(MANIFEST_DIR).join("tests/fixtures/entry_file.rs"))
+        .remove_doc_comments(remove_doc_comments)
+        .remove_test_modules(remove_test_modules);
+    bundler
+}
+
 fn actual_output(remove_doc_comments: bool, remove_test_modules: bool) -> String {
-    let config = Config {
-        crate_ident: "library".to_owned(),
-        crate_src_dir: Path::new(MANIFEST_DIR).join("tests/fixtures/library/src"),
-        entry_file_path: Some(Path::new(MANIFEST_DIR).join("tests/fixtures/entry_file.rs")),
-        remove_doc_comments,
-        remove_test_modules,
-        remove_comments: false,
-        indent_spaces: 4,
-    };
     let mut buf = vec![];
-    bundle(config, &mut buf).unwrap();
+    bundler(remove_doc_comments, remove_test_modules).bundle(&mut buf).unwrap();
     remove_empty_lines(&String::from_utf8(buf).unwrap())
 }
 
@@ -58,3 +76,810 @@ fn remove_doc_comments_remove_test_modules() {
     let actual = actual_output(true, true);
     assert_eq!(actual, expected);
 }
+
+#[test]
+fn source_map() {
+    let dir = temp_dir("source-map", &[]);
+    let source_map_path = dir.join("map.json");
+    let mut buf = vec![];
+    bundler(false, false).source_map(&source_map_path).bundle(&mut buf).unwrap();
+    let output = String::from_utf8(buf).unwrap();
+    let source_map = SourceMap::load(&source_map_path).unwrap();
+    fs::write(&source_map_path, "{").unwrap();
+    let err = SourceMap::load(&source_map_path).unwrap_err();
+    assert!(matches!(err, Error::SourceMapParse { .. }));
+    let err = SourceMap::load(&dir.join("missing.json")).unwrap_err();
+    assert!(matches!(err, Error::Read { .. }));
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(source_map.lines.len(), output.lines().count());
+    let line = output.lines().position(|line| line.contains("pub fn full_adder")).unwrap() + 1;
+    let location = source_map.lookup(line).unwrap();
+    assert_eq!(location.module, "crate::adders::full_adder");
+    assert!(location.file.ends_with("adders/full_adder.rs"));
+    assert_eq!(location.line, 5);
+    assert!(source_map.lookup(1).is_none());
+}
+
+#[test]
+fn provenance_comments() {
+    let raw_output = |bundler: &Bundler| {
+        let mut buf = vec![];
+        bundler.bundle(&mut buf).unwrap();
+        String::from_utf8(buf).unwrap()
+    };
+    let without_comments = raw_output(&bundler(false, false));
+    let with_comments =
+        raw_output(bundler(false, false).provenance_comments(true).provenance_commit("0123abc"));
+
+    let mut lines = with_comments.lines();
+    assert_eq!(lines.next(), Some("mod library { // src/lib.rs @ 0123abc"));
+    assert!(lines.any(|line| line == "    pub mod adders { // src/adders.rs @ 0123abc"));
+    assert_eq!(with_comments.lines().count(), without_comments.lines().count());
+}
+
+#[test]
+fn normalize() {
+    let expected = expected_output("normalize");
+    let mut buf = vec![];
+    bundler(true, true)
+        .max_blank_lines(1)
+        .trim_trailing_whitespace(true)
+        .trim_blank_lines_around_braces(true)
+        .bundle(&mut buf)
+        .unwrap();
+    let actual = String::from_utf8(buf).unwrap();
+    assert_eq!(actual, expected);
+}
+
+fn copy_with_crlf_and_bom(from: &Path, to: &Path) {
+    fs::create_dir_all(to).unwrap();
+    for entry in fs::read_dir(from).unwrap() {
+        let entry = entry.unwrap();
+        let to = to.join(entry.file_name());
+        if entry.file_type().unwrap().is_dir() {
+            copy_with_crlf_and_bom(&entry.path(), &to);
+        } else {
+            let content = fs::read_to_string(entry.path()).unwrap();
+            fs::write(to, format!("\u{feff}{}", content.replace('\n', "\r\n"))).unwrap();
+        }
+    }
+}
+
+#[test]
+fn crlf_and_bom() {
+    let dir = temp_dir("crlf-and-bom", &[]);
+    copy_with_crlf_and_bom(&Path::new(MANIFEST_DIR).join("tests/fixtures/library/src"), &dir);
+    let entry_file_path = dir.join("entry_file.rs");
+    let entry_content =
+        fs::read_to_string(Path::new(MANIFEST_DIR).join("tests/fixtures/entry_file.rs")).unwrap();
+    fs::write(&entry_file_path, format!("\u{feff}{}", entry_content.replace('\n', "\r\n")))
+        .unwrap();
+
+    let mut buf = vec![];
+    library(&dir)
+        .entry_file(entry_file_path)
+        .line_ending(LineEnding::Crlf)
+        .bundle(&mut buf)
+        .unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    let actual = String::from_utf8(buf).unwrap();
+
+    assert!(!actual.replace("\r\n", "").contains('\n'));
+    let actual = remove_empty_lines(&actual.replace("\r\n", "\n"));
+    assert_eq!(actual, expected_output("no-options"));
+}
+
+#[test]
+fn explain_chain() {
+    let mut buf = vec![];
+    bundler(false, false).explain("crate::gates::xor", &mut buf).unwrap();
+    let actual = String::from_utf8(buf).unwrap();
+    let lines = actual.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 3);
+    assert!(lines[0].ends_with(
+        "entry_file.rs:1: `use` of `crate::adders::full_adder` requires `crate::adders::full_adder`"
+    ));
+    assert_eq!(
+        lines[1],
+        "src/adders/full_adder.rs:1: `use` of `crate::adders::half_adder` requires `crate::adders::half_adder`",
+    );
+    assert_eq!(
+        lines[2],
+        "src/adders/half_adder.rs:1: `use` of `crate::gates::xor_gate` requires `crate::gates::xor`",
+    );
+
+    let mut buf = vec![];
+    assert!(bundler(false, false).explain("crate::gates::nand", &mut buf).is_err());
+}
+
+#[test]
+fn graph() {
+    let mut buf = vec![];
+    bundler(false, false).emit_graph(GraphFormat::Json, &mut buf).unwrap();
+    let graph = serde_json::from_slice::<serde_json::Value>(&buf).unwrap();
+
+    let nodes = graph["nodes"].as_array().unwrap();
+    let paths = nodes.iter().map(|node| node["path"].as_str().unwrap()).collect::<Vec<_>>();
+    assert_eq!(paths.len(), 9);
+    assert!(!paths.contains(&"crate::gates::nand"));
+    assert_eq!(nodes[0]["file"], "src/lib.rs");
+
+    let edges = graph["edges"].as_array().unwrap();
+    assert!(edges.iter().any(|edge| {
+        edge["from"] == "crate::adders::full_adder"
+            && edge["to"] == "crate::gates::or"
+            && edge["kind"] == "use"
+            && edge["line"] == 2
+    }));
+
+    let mut buf = vec![];
+    let bundler = library(&Path::new(MANIFEST_DIR).join("tests/fixtures/library/src"));
+    bundler.emit_graph(GraphFormat::Dot, &mut buf).unwrap();
+    let dot = String::from_utf8(buf).unwrap();
+    assert!(dot.starts_with("digraph modules {"));
+    assert!(dot.contains(r#""crate::gates" -> "crate::gates::nand" [label="mod"];"#));
+}
+
+#[test]
+fn report() {
+    let mut buf = vec![];
+    let report = bundler(true, true).remove_comments(true).bundle(&mut buf).unwrap();
+    let output = String::from_utf8(buf).unwrap();
+
+    let paths = report.included_modules.iter().map(|module| &*module.path).collect::<Vec<_>>();
+    assert_eq!(paths[0], "crate");
+    assert!(paths.contains(&"crate::gates::xor"));
+    assert!(report.removed_modules.contains(&"crate::gates::nand".to_owned()));
+    let bytes = report.included_modules.iter().map(|module| module.bytes).sum::<usize>();
+    assert!(0 < bytes && bytes <= output.len());
+    assert!(0 < report.bytes_saved.doc_comments);
+    assert!(0 < report.bytes_saved.test_modules);
+}
+
+/// Writes the files into a new temporary directory and returns the directory. The directory is
+/// named after the process as well so that concurrent test runs do not collide.
+fn temp_dir(name: &str, files: &[(&str, &str)]) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("cargo-simple-bundler-{}-{}", name, process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    for (path, content) in files {
+        let path = dir.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+    dir
+}
+
+/// Returns the directory of a crate which causes warnings and the bundler of it.
+fn crate_with_warnings(name: &str) -> (std::path::PathBuf, Bundler) {
+    let dir = temp_dir(
+        name,
+        &[
+            ("entry_file.rs", "use library::a::{A, Missing};\n"),
+            ("src/lib.rs", "pub mod a;\nmod inline {}\n"),
+            ("src/a.rs", "use super::super::B;\npub struct A;\n"),
+        ],
+    );
+    let mut bundler = library(&dir.join("src"));
+    bundler.entry_file(dir.join("entry_file.rs"));
+    (dir, bundler)
+}
+
+#[test]
+fn warnings() {
+    let (dir, bundler) = crate_with_warnings("warnings");
+    let mut buf = vec![];
+    let report = bundler.bundle(&mut buf).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    let find = |kind| report.warnings.iter().find(|warning| warning.kind == kind).unwrap();
+    let location = find(DiagnosticKind::InlineModule).location.as_ref().unwrap();
+    assert_eq!((location.file.as_path(), location.line), (&*dir.join("src/lib.rs"), 2));
+    assert_eq!(location.column, 4);
+    assert_eq!(location.snippet, "mod inline {}");
+    let location = find(DiagnosticKind::TooManySuper).location.as_ref().unwrap();
+    assert_eq!((location.file.as_path(), location.line), (&*dir.join("src/a.rs"), 1));
+    let location = find(DiagnosticKind::DefinitionNotFound).location.as_ref().unwrap();
+    assert_eq!(location.file, dir.join("entry_file.rs"));
+    assert!(find(DiagnosticKind::DefinitionNotFound).to_string().contains("1 | use library::a"));
+}
+
+#[test]
+fn graph_warnings() {
+    let dir = temp_dir(
+        "graph-warnings",
+        &[
+            ("entry_file.rs", "use library::a::A;\n"),
+            ("src/lib.rs", "pub mod a;\npub mod b;\n"),
+            ("src/a.rs", "use crate::b::Missing;\npub struct A;\n"),
+            ("src/b.rs", "pub struct B;\n"),
+        ],
+    );
+    let mut bundler = library(&dir.join("src"));
+    bundler.entry_file(dir.join("entry_file.rs"));
+    let report = bundler.bundle(&mut vec![]).unwrap();
+    let warnings = bundler.emit_graph(GraphFormat::Json, &mut vec![]).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(report.warnings.len(), 1);
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(warnings[0].to_string(), report.warnings[0].to_string());
+}
+
+#[test]
+fn deny_warnings() {
+    let (dir, mut bundler) = crate_with_warnings("deny-warnings");
+    let mut buf = vec![];
+    bundler.deny_warnings(true);
+    let err = bundler.bundle(&mut buf).unwrap_err().to_string();
+    assert!(err.starts_with("aborting due to 3 denied warning(s)"));
+    assert!(err.contains("warning[too-many-super]"));
+    assert!(buf.is_empty());
+    let err = bundler.explain("crate::a", &mut buf).unwrap_err();
+    assert!(matches!(err, Error::DeniedWarnings(warnings) if warnings.len() == 3));
+    let err = bundler.emit_graph(GraphFormat::Json, &mut buf).unwrap_err();
+    assert!(matches!(err, Error::DeniedWarnings(warnings) if warnings.len() == 3));
+    assert!(buf.is_empty());
+
+    bundler
+        .allow(DiagnosticKind::DefinitionNotFound)
+        .allow(DiagnosticKind::InlineModule)
+        .allow(DiagnosticKind::TooManySuper);
+    let report = bundler.bundle(&mut buf).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(report.warnings.is_empty());
+    assert!(!buf.is_empty());
+}
+
+#[test]
+fn located_errors() {
+    let dir = temp_dir(
+        "located-errors",
+        &[("src/lib.rs", "pub mod a;\n"), ("src/a.rs", "pub struct A;\nfn f() { g() h }\n")],
+    );
+    let bundler = library(&dir.join("src"));
+    let err = bundler.bundle(&mut vec![]).unwrap_err().to_string();
+    let expected = format!(
+        "\
+failed to parse the module `crate::a`: unexpected token, expected `;`
+ --> {}:2:14
+  |
+2 | fn f() {{ g() h }}
+  |              ^
+note: the module is declared at {}:1:1",
+        dir.join("src/a.rs").display(),
+        dir.join("src/lib.rs").display(),
+    );
+    assert_eq!(err, expected);
+
+    fs::write(dir.join("src/lib.rs"), "\nmod missing;\n").unwrap();
+    let err = bundler.bundle(&mut vec![]).unwrap_err();
+    match &err {
+        Error::ModuleMissing { module, file, declared_at, .. } => {
+            assert_eq!(module, "crate::missing");
+            assert_eq!(file, &dir.join("src/missing.rs"));
+            assert_eq!(declared_at.as_ref().unwrap().line, 2);
+        }
+        _ => panic!("unexpected error: {}", err),
+    }
+    let err = err.to_string();
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(err.starts_with("failed to read `"));
+    assert!(err.contains("for the module `crate::missing`"));
+    assert!(err.ends_with(&format!("declared at {}:2:1", dir.join("src/lib.rs").display())));
+}
+
+#[test]
+fn entry_parse_error() {
+    let dir = temp_dir(
+        "entry-parse-error",
+        &[("entry_file.rs", "use library::gates;\nfn main() { let }\n")],
+    );
+    let err = bundler(false, false).entry_file(dir.join("entry_file.rs")).bundle(&mut vec![]);
+    let err = err.unwrap_err();
+    fs::remove_dir_all(&dir).unwrap();
+    match &err {
+        Error::EntryParse { location, .. } => {
+            assert_eq!(location.file, dir.join("entry_file.rs"));
+            assert_eq!((location.line, &*location.snippet), (2, "fn main() { let }"));
+        }
+        _ => panic!("unexpected error: {}", err),
+    }
+    assert!(err.to_string().starts_with("failed to parse the entry file: "));
+
+    fn assert_error<E: std::error::Error + Send + Sync + 'static>(_: &E) {}
+    assert_error(&err);
+}
+
+#[test]
+fn from_manifest() {
+    let manifest_path = Path::new(MANIFEST_DIR).join("tests/fixtures/library/Cargo.toml");
+    let mut bundler = Bundler::from_manifest(Some(&manifest_path)).unwrap();
+    assert!(bundler.config().cache_dir.as_ref().unwrap().ends_with("target/cargo-simple-bundler"));
+    // keep the cache out of the source tree
+    bundler.cache_dir(None);
+    assert_eq!(bundler.config().crate_ident, "library");
+    assert!(bundler.config().crate_src_dir.ends_with("tests/fixtures/library/src"));
+
+    let mut buf = vec![];
+    bundler
+        .entry_file(Path::new(MANIFEST_DIR).join("tests/fixtures/entry_file.rs"))
+        .indent(IndentStyle::Spaces(4))
+        .bundle(&mut buf)
+        .unwrap();
+    let actual = remove_empty_lines(&String::from_utf8(buf).unwrap());
+    assert_eq!(actual, expected_output("no-options"));
+
+    assert_eq!(bundler.config().edition, "2018");
+
+    let dir = temp_dir(
+        "from-manifest",
+        &[
+            (
+                "Cargo.toml",
+                "[package]\nname = \"library\"\nversion = \"0.0.0\"\nedition = \"2021\"\n\n\
+                 [workspace]\n",
+            ),
+            ("src/lib.rs", ""),
+        ],
+    );
+    let bundler = Bundler::from_manifest(Some(&dir.join("Cargo.toml")));
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(bundler.unwrap().config().edition, "2021");
+
+    let err = Bundler::from_manifest(Some(Path::new("/nonexistent/Cargo.toml"))).unwrap_err();
+    assert!(matches!(err, Error::Metadata(_)));
+}
+
+#[test]
+fn memory_sources() {
+    let mut sources = MemorySources::new();
+    sources
+        .insert("/virtual/src/lib.rs", "pub mod a;\npub mod b;\n")
+        .insert("/virtual/src/a.rs", "pub struct A;\n")
+        .insert("/virtual/src/b.rs", "pub struct B;\n");
+    let mut bundler = library(Path::new("/virtual/src"));
+    bundler.sources(sources.clone()).entry_source("use library::a::A;\nfn main() {}\n");
+    let mut buf = vec![];
+    let report = bundler.bundle(&mut buf).unwrap();
+    let output = String::from_utf8(buf).unwrap();
+    assert!(output.contains("pub struct A;"));
+    assert!(!output.contains("pub struct B;"));
+    assert_eq!(report.removed_modules, ["crate::b"]);
+
+    sources.insert("/virtual/src/lib.rs", "pub mod a;\npub mod c;\n");
+    bundler
+        .sources(sources)
+        .entry_file("main.rs")
+        .entry_source("use library::c::C;\nfn main() {}\n");
+    let err = bundler.bundle(&mut vec![]).unwrap_err();
+    match err {
+        Error::ModuleMissing { module, file, .. } => {
+            assert_eq!(module, "crate::c");
+            assert_eq!(file, Path::new("/virtual/src/c.rs"));
+        }
+        _ => panic!("unexpected error: {}", err),
+    }
+}
+
+#[test]
+fn macro_items() {
+    let mut sources = MemorySources::new();
+    sources
+        .insert("/virtual/src/lib.rs", "pub mod a;\npub mod b;\n")
+        .insert("/virtual/src/a.rs", "#[doc(hidden)]\npub macro m() {}\n")
+        .insert("/virtual/src/b.rs", "pub struct B;\n");
+    let mut bundler = library(Path::new("/virtual/src"));
+    bundler.sources(sources.clone()).entry_source("use library::a::m;\nfn main() {}\n");
+    let mut buf = vec![];
+    let report = bundler.bundle(&mut buf).unwrap();
+    assert!(String::from_utf8(buf).unwrap().contains("pub macro m() {}"));
+    assert_eq!(report.removed_modules, ["crate::b"]);
+    assert!(report.unresolved_symbols.is_empty());
+
+    sources.insert("/virtual/src/b.rs", "crate use crate::a::m;\n");
+    bundler.sources(sources).entry_source("use library::b;\nfn main() {}\n");
+    let err = bundler.bundle(&mut vec![]).unwrap_err().to_string();
+    assert!(err.starts_with(
+        "failed to parse the module `crate::b`: \
+         the `crate` visibility is not supported; use `pub(crate)` instead\n \
+         --> /virtual/src/b.rs:1:7\n"
+    ));
+}
+
+#[test]
+fn check() {
+    let mut buf = vec![];
+    bundler(false, false).check(true).bundle(&mut buf).unwrap();
+    assert!(!buf.is_empty());
+    // `rustc` fails without errors in JSON
+    let err = bundler(false, false).edition("2099").check(true).bundle(&mut vec![]).unwrap_err();
+    assert!(err.to_string().contains("--edition"));
+
+    let dir = temp_dir(
+        "check",
+        &[
+            ("src/lib.rs", "pub mod a;\n"),
+            ("src/a.rs", "pub fn f() -> u32 {\n    \"\"\n}\n"),
+            ("main.rs", "use library::a::f;\n\nfn main() {\n    f();\n}\n"),
+        ],
+    );
+    let mut buf = vec![];
+    let err = library(&dir.join("src"))
+        .entry_file(dir.join("main.rs"))
+        .check(true)
+        .bundle(&mut buf)
+        .unwrap_err();
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(buf.is_empty());
+    match &err {
+        Error::CompileFailed(messages) => {
+            assert_eq!(messages.len(), 1);
+            match &messages[0].origin {
+                Some(MessageOrigin::Library(location)) => {
+                    assert_eq!(location.module, "crate::a");
+                    assert_eq!(location.file, dir.join("src/a.rs"));
+                    assert_eq!(location.line, 2);
+                }
+                origin => panic!("unexpected origin: {:?}", origin),
+            }
+        }
+        _ => panic!("unexpected error: {}", err),
+    }
+    assert!(err
+        .to_string()
+        .contains(&format!("note: from {}:2 (crate::a)", dir.join("src/a.rs").display())));
+}
+
+#[test]
+fn test_bundle() {
+    let dir = temp_dir(
+        "test-bundle",
+        &[
+            (
+                "Cargo.toml",
+                "[package]\nname = \"library\"\nversion = \"0.0.0\"\nedition = \"2018\"\n\n\
+                 [workspace]\n\n[dependencies]\ntwo = { path = \"two\", package = \"dep\" }\n",
+            ),
+            ("two/Cargo.toml", "[package]\nname = \"dep\"\nversion = \"0.1.0\"\n"),
+            ("two/src/lib.rs", "pub fn two() -> u32 {\n    2\n}\n"),
+            (
+                "src/lib.rs",
+                "pub fn one() -> u32 {\n    1\n}\n\n#[cfg(test)]\nmod tests {\n    \
+                 #[test]\n    fn one() {\n        assert_eq!(super::one(), 1);\n    }\n\n    \
+                 #[test]\n    fn two() {\n        assert_eq!(two::two(), 2);\n    }\n\n    \
+                 #[test]\n    fn module_path() {\n        \
+                 assert_eq!(module_path!(), \"library::tests\");\n    }\n\n    \
+                 #[test]\n    fn broken() {\n        assert_eq!(super::one(), 2);\n    }\n\n    \
+                 #[test]\n    fn z_abort() {\n        std::process::abort();\n    }\n}\n",
+            ),
+        ],
+    );
+    let report = library(&dir.join("src")).remove_test_modules(true).test();
+    fs::remove_dir_all(&dir).unwrap();
+    let report = report.unwrap();
+    assert_eq!(report.passed, ["tests::one", "tests::two"]);
+    assert_eq!(report.failed_only_bundled, ["tests::module_path"]);
+    assert_eq!(report.failed_in_both, ["tests::broken", "tests::z_abort"]);
+}
+
+#[test]
+fn watcher() {
+    let (dir, bundler) = crate_with_warnings("watcher");
+    let touch = |path: &str, content: &str| {
+        let path = dir.join(path);
+        fs::write(&path, content).unwrap();
+        let mtime = std::time::SystemTime::now() + std::time::Duration::from_secs(10);
+        fs::File::options().write(true).open(path).unwrap().set_modified(mtime).unwrap();
+    };
+    let mut watcher = bundler.watcher();
+    assert!(watcher.poll());
+    let mut buf = vec![];
+    let warnings = watcher.bundle(&mut buf).unwrap().warnings;
+    assert!(String::from_utf8(buf).unwrap().contains("pub struct A;"));
+    assert!(!watcher.poll());
+
+    // the warnings of the files reused from the previous bundling are reported again
+    touch("entry_file.rs", "use library::a::{A, Missing};\nfn main() {}\n");
+    assert!(watcher.poll());
+    let again = watcher.bundle(&mut vec![]).unwrap().warnings;
+    let kinds = |warnings: &[cargo_simple_bundler::Diagnostic]| {
+        warnings.iter().map(|w| (w.kind, w.message.clone())).collect::<Vec<_>>()
+    };
+    assert_eq!(kinds(&again), kinds(&warnings));
+
+    touch("src/a.rs", "pub struct A(u32);\n");
+    assert!(watcher.poll());
+    let mut buf = vec![];
+    let report = watcher.bundle(&mut buf).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(String::from_utf8(buf).unwrap().contains("pub struct A(u32);"));
+    assert!(report.warnings.iter().all(|w| w.kind != DiagnosticKind::TooManySuper));
+}
+
+/// Reads the files from the disk, and modifies `a.rs` right after reading it for the first time.
+#[derive(Debug, Default)]
+struct EditingSources(AtomicBool);
+
+impl SourceProvider for EditingSources {
+    fn read(&self, path: &Path) -> io::Result<String> {
+        let content = fs::read_to_string(path)?;
+        if path.ends_with("a.rs") && !self.0.swap(true, Ordering::SeqCst) {
+            fs::write(path, "pub struct A(u64);\n")?;
+        }
+        Ok(content)
+    }
+}
+
+#[test]
+fn watcher_edit_during_bundle() {
+    let (dir, mut bundler) = crate_with_warnings("watcher-edit-during-bundle");
+    bundler.sources(EditingSources::default());
+    let mut watcher = bundler.watcher();
+    assert!(watcher.poll());
+    let mut buf = vec![];
+    watcher.bundle(&mut buf).unwrap();
+    assert!(String::from_utf8(buf).unwrap().contains("pub struct A;"));
+
+    // the edit saved while bundling is picked up
+    assert!(watcher.poll());
+    let mut buf = vec![];
+    watcher.bundle(&mut buf).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(String::from_utf8(buf).unwrap().contains("pub struct A(u64);"));
+}
+
+#[test]
+fn cache() {
+    let cache_dir = temp_dir("cache", &[]);
+    for &(remove_doc_comments, remove_test_modules, name) in &[
+        (false, false, "no-options"),
+        (true, true, "remove-doc-comments-remove-test-modules"),
+        (false, false, "no-options"),
+    ] {
+        let mut buf = vec![];
+        bundler(remove_doc_comments, remove_test_modules)
+            .cache_dir(Some(cache_dir.clone()))
+            .bundle(&mut buf)
+            .unwrap();
+        let actual = remove_empty_lines(&String::from_utf8(buf).unwrap());
+        assert_eq!(actual, expected_output(name));
+    }
+
+    // the analysis is read from the cache, not from the file
+    let (dir, mut bundler) = crate_with_warnings("cache-warnings");
+    bundler.cache_dir(Some(cache_dir.clone()));
+    let warnings = bundler.bundle(&mut vec![]).unwrap().warnings;
+    for entry in fs::read_dir(&cache_dir).unwrap() {
+        let path = entry.unwrap().path();
+        let json = fs::read_to_string(&path).unwrap();
+        fs::write(&path, json.replace("skip the inline module", "SKIP THE INLINE MODULE")).unwrap();
+    }
+    let cached = bundler.bundle(&mut vec![]).unwrap().warnings;
+    assert_eq!(cached.len(), warnings.len());
+    assert!(cached.iter().any(|w| w.message.starts_with("SKIP THE INLINE MODULE")));
+
+    // the entry of the old content of a file is replaced
+    let count = || fs::read_dir(&cache_dir).unwrap().count();
+    let before = count();
+    fs::write(dir.join("src/a.rs"), "pub struct A(u32);\n").unwrap();
+    bundler.bundle(&mut vec![]).unwrap();
+    assert_eq!(count(), before);
+
+    // a broken cache is ignored
+    for entry in fs::read_dir(&cache_dir).unwrap() {
+        fs::write(entry.unwrap().path(), "{").unwrap();
+    }
+    let again = bundler.bundle(&mut vec![]).unwrap().warnings;
+    fs::remove_dir_all(&dir).unwrap();
+    fs::remove_dir_all(&cache_dir).unwrap();
+    assert!(again.iter().any(|w| w.message.starts_with("skip the inline module")));
+}
+
+#[test]
+fn parallel_loading() {
+    let mut files = vec![("src/lib.rs".to_owned(), String::new())];
+    for i in 0..8 {
+        files[0].1 += &format!("pub mod m{};\n", i);
+        files.push((
+            format!("src/m{}.rs", i),
+            format!("pub mod a;\npub mod b;\npub struct M{};\n", i),
+        ));
+        files.push((format!("src/m{}/a.rs", i), format!("pub struct A{};\nmod inline {{}}\n", i)));
+        files.push((format!("src/m{}/b.rs", i), format!("pub struct B{};\n", i)));
+    }
+    let files = files.iter().map(|(path, content)| (&**path, &**content)).collect::<Vec<_>>();
+    let dir = temp_dir("parallel-loading", &files);
+
+    let mut buf = vec![];
+    let report = library(&dir.join("src")).bundle(&mut buf).unwrap();
+    let output = String::from_utf8(buf).unwrap();
+    for i in 0..8 {
+        for item in &[format!("M{}", i), format!("A{}", i), format!("B{}", i)] {
+            assert!(output.contains(&format!("pub struct {};", item)));
+        }
+    }
+    assert_eq!(report.included_modules.len(), 25);
+    assert_eq!(report.warnings.len(), 8);
+
+    // the errors of the files loaded in advance are reported when they are used
+    fs::remove_file(dir.join("src/m3/b.rs")).unwrap();
+    let err = library(&dir.join("src")).bundle(&mut vec![]).unwrap_err();
+    fs::remove_dir_all(&dir).unwrap();
+    match err {
+        Error::ModuleMissing { module, declared_at, .. } => {
+            assert_eq!(module, "crate::m3::b");
+            assert_eq!(declared_at.unwrap().line, 2);
+        }
+        _ => panic!("unexpected error: {}", err),
+    }
+}
+
+#[test]
+fn deterministic_output() {
+    let mut files = vec![
+        ("entry_file.rs".to_owned(), String::new()),
+        ("src/lib.rs".to_owned(), "pub mod shared;\n".to_owned()),
+        ("src/shared.rs".to_owned(), "pub struct Shared;\n".to_owned()),
+    ];
+    for i in 0..8 {
+        files[1].1 += &format!("pub mod m{};\n", i);
+        if i % 2 == 0 {
+            files[0].1 += &format!("use library::m{}::{{M{}, Missing{}}};\n", i, i, i);
+        }
+        files.push((
+            format!("src/m{}.rs", i),
+            format!("use super::shared::{{Shared, Unknown{}}};\npub struct M{}(Shared);\n", i, i),
+        ));
+    }
+    let files = files.iter().map(|(path, content)| (&**path, &**content)).collect::<Vec<_>>();
+    let dir = temp_dir("deterministic-output", &files);
+    let mut bundler = library(&dir.join("src"));
+    bundler.entry_file(dir.join("entry_file.rs"));
+
+    let run = || {
+        let mut output = vec![];
+        let report = bundler.bundle(&mut output).unwrap();
+        let mut graph = vec![];
+        bundler.emit_graph(GraphFormat::Json, &mut graph).unwrap();
+        let mut explain = vec![];
+        bundler.explain("crate::shared", &mut explain).unwrap();
+        (output, serde_json::to_string(&report).unwrap(), graph, explain)
+    };
+    let first = run();
+    for _ in 0..4 {
+        assert!(run() == first);
+    }
+    fs::remove_dir_all(&dir).unwrap();
+
+    let report = serde_json::from_str::<serde_json::Value>(&first.1).unwrap();
+    assert_eq!(
+        report["removed_modules"],
+        serde_json::json!(["crate::m1", "crate::m3", "crate::m5", "crate::m7"])
+    );
+    assert_eq!(report["warnings"].as_array().unwrap().len(), 8);
+}
+
+#[test]
+fn multibyte_text() {
+    let mut sources = MemorySources::new();
+    sources
+        .insert("/virtual/src/lib.rs", "pub mod a;\npub mod b;\n")
+        .insert(
+            "/virtual/src/a.rs",
+            "/// 日本語のドキュメント\n\
+             pub struct A; // 構造体\n\
+             pub const S: &str = \"文字列\"; use crate::b::B;\n\
+             macro_rules! m { () => { \"α\"; $crate::b::B }; }\n",
+        )
+        .insert("/virtual/src/b.rs", "pub struct B; /* β */ /** γ */ pub struct C;\n");
+    let mut bundler = library(Path::new("/virtual/src"));
+    bundler.sources(sources).remove_doc_comments(true);
+    let mut buf = vec![];
+    bundler.bundle(&mut buf).unwrap();
+    let output = String::from_utf8(buf).unwrap();
+    assert!(!output.contains("日本語"));
+    assert!(output.contains("        pub struct A; // 構造体\n"));
+    assert!(output.contains("\"文字列\"; use crate::library::b::B;\n"));
+    assert!(output.contains("{ \"α\"; $crate::library::b::B }"));
+    // the removed doc comment is replaced with as many spaces as its characters
+    assert!(output.contains(&format!("pub struct B; /* β */ {} pub struct C;\n", " ".repeat(8))));
+
+    bundler.remove_doc_comments(false).remove_comments(true);
+    let mut buf = vec![];
+    let report = bundler.bundle(&mut buf).unwrap();
+    let output = String::from_utf8(buf).unwrap();
+    assert!(output.contains("        /// 日本語のドキュメント\n        pub struct A;\n"));
+    assert!(output.contains(&format!("pub struct B; {} /** γ */ pub struct C;\n", " ".repeat(7))));
+    assert_eq!(report.bytes_saved.comments, "//構造体/*β*/".len());
+}
+
+/// Options of a scenario, read from `options.json` in its directory.
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ScenarioOptions {
+    remove_doc_comments: bool,
+    remove_test_modules: bool,
+    remove_comments: bool,
+    max_blank_lines: Option<usize>,
+    trim_trailing_whitespace: bool,
+    trim_blank_lines_around_braces: bool,
+}
+
+/// Bundles the scenario in `dir` and renders the output followed by the report, with the paths
+/// relative to `dir` so that the snapshot does not depend on where the repository is.
+fn render_scenario(dir: &Path) -> String {
+    let options = fs::read_to_string(dir.join("options.json")).unwrap();
+    let options = serde_json::from_str::<ScenarioOptions>(&options).unwrap();
+    let mut bundler = library(&dir.join("src"));
+    bundler
+        .entry_file(dir.join("entry_file.rs"))
+        .remove_doc_comments(options.remove_doc_comments)
+        .remove_test_modules(options.remove_test_modules)
+        .remove_comments(options.remove_comments)
+        .trim_trailing_whitespace(options.trim_trailing_whitespace)
+        .trim_blank_lines_around_braces(options.trim_blank_lines_around_braces);
+    if let Some(max) = options.max_blank_lines {
+        bundler.max_blank_lines(max);
+    }
+    let mut buf = vec![];
+    let report = bundler.bundle(&mut buf).unwrap();
+
+    let mut rendered = String::from_utf8(buf).unwrap();
+    rendered.push_str("---\n");
+    for module in &report.removed_modules {
+        writeln!(rendered, "removed: {}", module).unwrap();
+    }
+    for symbol in &report.unresolved_symbols {
+        writeln!(rendered, "unresolved: {} -> {}", symbol.path, symbol.fallback).unwrap();
+    }
+    for warning in &report.warnings {
+        write!(rendered, "warning[{}]", warning.kind.as_str()).unwrap();
+        if let Some(location) = &warning.location {
+            let mut location = location.clone();
+            location.file = location.file.strip_prefix(dir).unwrap().to_owned();
+            write!(rendered, " {}", location.short()).unwrap();
+        }
+        writeln!(rendered, ": {}", warning.message).unwrap();
+    }
+    rendered
+}
+
+/// Runs every directory under `tests/fixtures/` with `options.json` as a scenario: the crate
+/// `library` in `src/` is bundled with `entry_file.rs` and compared with `expected.txt`. Set
+/// `BLESS=1` to write the actual results to `expected.txt` instead.
+#[test]
+fn fixtures() {
+    let bless = matches!(env::var_os("BLESS"), Some(value) if !value.is_empty() && value != "0");
+    let mut dirs = fs::read_dir(Path::new(MANIFEST_DIR).join("tests/fixtures"))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|dir| dir.join("options.json").is_file())
+        .collect::<Vec<_>>();
+    dirs.sort();
+    assert!(!dirs.is_empty());
+
+    let mut failures = vec![];
+    for dir in &dirs {
+        let actual = render_scenario(dir);
+        let expected_path = dir.join("expected.txt");
+        if bless {
+            fs::write(&expected_path, &actual).unwrap();
+            continue;
+        }
+        let expected = fs::read_to_string(&expected_path).unwrap_or_default();
+        if actual != expected {
+            let name = dir.file_name().unwrap().to_string_lossy().into_owned();
+            eprintln!(
+                "=== {}: expected ===\n{}=== {}: actual ===\n{}",
+                name, expected, name, actual
+            );
+            failures.push(name);
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "snapshots differ (rerun with `BLESS=1` to update): {:?}",
+        failures
+    );
+}